@@ -1,12 +1,24 @@
 use std::fmt::Display;
 
 use crate::{
-    dram::Dram,
+    clint::{Clint, CLINT_BASE, CLINT_SIZE},
+    config::MachineConfig,
+    device::Device,
+    htif::Htif,
+    memory::Memory,
+    plic::{Plic, PLIC_BASE, PLIC_SIZE, PLIC_SOURCES},
     processor::{ProcessorError, ProcessorErrorTrait},
+    uart::{Uart, UART_BASE, UART_IRQ, UART_SIZE},
 };
 
 pub enum BusErrorType {
-    AddressOutOfBounds,
+    // どのデバイスにも割り当てられていないアドレスへのアクセス
+    UnmappedAddress(u32),
+    InvalidRange { base: u32, size: u32 },
+    OverlappingRange { base: u32, size: u32 },
+    InvalidInterruptSource(u32),
+    // デバッガからメモリ以外のデバイスへのアクセス
+    DebugAccessToDevice(u32),
 }
 
 pub struct BusError {
@@ -24,76 +36,380 @@ impl ProcessorErrorTrait for BusError {}
 impl Display for BusError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.error_type {
-            BusErrorType::AddressOutOfBounds => write!(f, "BusAddressOutOfBounds"),
+            BusErrorType::UnmappedAddress(address) => {
+                write!(f, "Unmapped bus address 0x{:08x}", address)
+            }
+            BusErrorType::InvalidRange { base, size } => {
+                write!(f, "Invalid device range 0x{:08x} (size 0x{:x})", base, size)
+            }
+            BusErrorType::OverlappingRange { base, size } => write!(
+                f,
+                "Device range 0x{:08x} (size 0x{:x}) overlaps an existing device",
+                base, size
+            ),
+            BusErrorType::InvalidInterruptSource(source) => {
+                write!(f, "Invalid interrupt source {}", source)
+            }
+            BusErrorType::DebugAccessToDevice(address) => {
+                write!(f, "Debug access to device at 0x{:08x}", address)
+            }
         }
     }
 }
 
 pub const DRAM_BASE: u32 = 0x80000000;
 
-#[derive(Debug, Clone)]
+// アドレスマップに登録されたデバイス。組み込みのデバイスは型のまま持つ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DeviceSlot {
+    Memory(usize),
+    Clint,
+    Plic,
+    Uart,
+    External(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Mapping {
+    base: u32,
+    size: u32,
+    slot: DeviceSlot,
+    // デバイスの割り込み線を繋ぐPLICのソース番号
+    interrupt: Option<u32>,
+}
+
+impl Mapping {
+    fn end(&self) -> u64 {
+        self.base as u64 + self.size as u64
+    }
+}
+
+#[derive(Debug)]
 pub struct Bus {
-    pub dram: Dram,
+    config: MachineConfig,
+    memory: Vec<Memory>,
+    pub htif: Option<Htif>,
+    // LR.Wで予約したワードのアドレス
+    pub reservation: Option<u32>,
+    pub clint: Clint,
+    pub plic: Plic,
+    pub uart: Uart,
+    devices: Vec<Box<dyn Device>>,
+    // 先頭アドレス順に並べたアドレスマップ
+    map: Vec<Mapping>,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new(&MachineConfig::default())
+    }
 }
 
 impl Bus {
-    pub fn new() -> Self {
-        Self { dram: Dram::new() }
+    pub fn new(config: &MachineConfig) -> Self {
+        let mut bus = Self {
+            config: config.clone(),
+            memory: Vec::new(),
+            htif: None,
+            reservation: None,
+            clint: Clint::new(),
+            plic: Plic::new(),
+            uart: Uart::new(),
+            devices: Vec::new(),
+            map: Vec::new(),
+        };
+
+        let memory = config
+            .memory()
+            .iter()
+            .enumerate()
+            .map(|(index, region)| (region.base, region.size, DeviceSlot::Memory(index), None));
+        let devices = [
+            (CLINT_BASE, CLINT_SIZE, DeviceSlot::Clint, None),
+            (PLIC_BASE, PLIC_SIZE, DeviceSlot::Plic, None),
+            (UART_BASE, UART_SIZE, DeviceSlot::Uart, Some(UART_IRQ)),
+        ];
+
+        for (base, size, slot, interrupt) in memory.chain(devices) {
+            let mapping = Mapping {
+                base,
+                size,
+                slot,
+                interrupt,
+            };
+            if bus.insert_mapping(mapping).is_err() {
+                unreachable!("MachineConfig keeps memory apart from built-in devices");
+            }
+        }
+        bus.memory = config
+            .memory()
+            .iter()
+            .map(|region| Memory::new(config.memory_backend(), region.size))
+            .collect();
+
+        bus
     }
 
-    pub fn load8(&mut self, start_address: u32, data: Vec<u8>) -> Result<(), ProcessorError> {
-        if start_address >= DRAM_BASE {
-            self.dram.load8(start_address - DRAM_BASE, data)
-        } else {
-            Err(BusError::new(BusErrorType::AddressOutOfBounds))
+    pub fn config(&self) -> &MachineConfig {
+        &self.config
+    }
+
+    // MachineConfigのメモリ領域と同じ順に並ぶ
+    pub fn memory(&self) -> &[Memory] {
+        &self.memory
+    }
+
+    // Sparseのメモリで書き込まれたページの先頭の物理アドレス。領域の順、領域内では昇順に並ぶ
+    pub fn touched_pages(&self) -> impl Iterator<Item = u32> + '_ {
+        self.config
+            .memory()
+            .iter()
+            .zip(&self.memory)
+            .filter_map(|(region, memory)| match memory {
+                Memory::Sparse(memory) => Some((region.base, memory)),
+                Memory::Flat(_) => None,
+            })
+            .flat_map(|(base, memory)| {
+                memory
+                    .touched_page_offsets()
+                    .map(move |offset| base + offset)
+            })
+    }
+
+    // 外部のデバイスを[base, base + size)に割り当てる。既存の領域と重なる場合はエラー
+    pub fn map_device(
+        &mut self,
+        base: u32,
+        size: u32,
+        device: Box<dyn Device>,
+    ) -> Result<(), ProcessorError> {
+        self.map_external(base, size, device, None)
+    }
+
+    // 割り込み線をPLICのsourceに繋いで割り当てる
+    pub fn map_device_with_interrupt(
+        &mut self,
+        base: u32,
+        size: u32,
+        device: Box<dyn Device>,
+        source: u32,
+    ) -> Result<(), ProcessorError> {
+        if source == 0 || source >= PLIC_SOURCES {
+            return Err(BusError::new(BusErrorType::InvalidInterruptSource(source)));
         }
+
+        self.map_external(base, size, device, Some(source))
     }
 
-    pub fn read8(&self, address: u32) -> Result<u8, ProcessorError> {
-        if address >= DRAM_BASE {
-            self.dram.read8(address - DRAM_BASE)
-        } else {
-            Err(BusError::new(BusErrorType::AddressOutOfBounds))
+    fn map_external(
+        &mut self,
+        base: u32,
+        size: u32,
+        device: Box<dyn Device>,
+        interrupt: Option<u32>,
+    ) -> Result<(), ProcessorError> {
+        self.insert_mapping(Mapping {
+            base,
+            size,
+            slot: DeviceSlot::External(self.devices.len()),
+            interrupt,
+        })?;
+        self.devices.push(device);
+        crate::trace!(
+            Device,
+            Info,
+            "Bus: mapped device at 0x{:0>8x} (size 0x{:x})",
+            base,
+            size
+        );
+
+        Ok(())
+    }
+
+    fn insert_mapping(&mut self, mapping: Mapping) -> Result<(), ProcessorError> {
+        let Mapping { base, size, .. } = mapping;
+        if size == 0 || mapping.end() > 1 << 32 {
+            return Err(BusError::new(BusErrorType::InvalidRange { base, size }));
         }
+        if self
+            .map
+            .iter()
+            .any(|other| (other.base as u64) < mapping.end() && (base as u64) < other.end())
+        {
+            return Err(BusError::new(BusErrorType::OverlappingRange { base, size }));
+        }
+
+        let index = self.map.partition_point(|other| other.base < base);
+        self.map.insert(index, mapping);
+
+        Ok(())
     }
 
-    pub fn read16(&self, address: u32) -> Result<u16, ProcessorError> {
-        if address >= DRAM_BASE {
-            self.dram.read16(address - DRAM_BASE)
-        } else {
-            Err(BusError::new(BusErrorType::AddressOutOfBounds))
+    // アクセス全体を含むデバイスと、その中でのオフセットを探す
+    fn find(&self, address: u32, width: u32) -> Result<(DeviceSlot, u32), ProcessorError> {
+        let index = self.map.partition_point(|mapping| mapping.base <= address);
+
+        index
+            .checked_sub(1)
+            .map(|index| self.map[index])
+            .filter(|mapping| address as u64 + width as u64 <= mapping.end())
+            .map(|mapping| (mapping.slot, address - mapping.base))
+            .ok_or_else(|| {
+                crate::trace!(Device, Debug, "Bus: unmapped access at 0x{:0>8x}", address);
+                BusError::new(BusErrorType::UnmappedAddress(address)) as ProcessorError
+            })
+    }
+
+    fn device_mut(&mut self, slot: DeviceSlot) -> &mut dyn Device {
+        match slot {
+            DeviceSlot::Memory(index) => &mut self.memory[index],
+            DeviceSlot::Clint => &mut self.clint,
+            DeviceSlot::Plic => &mut self.plic,
+            DeviceSlot::Uart => &mut self.uart,
+            DeviceSlot::External(index) => self.devices[index].as_mut(),
         }
     }
 
-    pub fn read32(&self, address: u32) -> Result<u32, ProcessorError> {
-        if address >= DRAM_BASE {
-            self.dram.read32(address - DRAM_BASE)
-        } else {
-            Err(BusError::new(BusErrorType::AddressOutOfBounds))
+    // 1サイクル進める。各デバイスを進め、割り込み線をPLICに反映する
+    pub fn tick(&mut self) {
+        for index in 0..self.map.len() {
+            let mapping = self.map[index];
+            let device = self.device_mut(mapping.slot);
+            device.tick();
+
+            if let Some(source) = mapping.interrupt {
+                let level = device.is_interrupting();
+                self.plic.set_interrupt_line(source, level);
+            }
         }
     }
 
-    pub fn write8(&mut self, address: u32, value: u8) -> Result<(), ProcessorError> {
-        if address >= DRAM_BASE {
-            self.dram.write8(address - DRAM_BASE, value)
-        } else {
-            Err(BusError::new(BusErrorType::AddressOutOfBounds))
+    pub fn htif_exit_code(&self) -> Option<u32> {
+        self.htif.and_then(|htif| htif.exit_code())
+    }
+
+    // tohostへのストアであればHTIFに通知する
+    fn notify_htif(&mut self, slot: DeviceSlot, address: u32) -> Result<(), ProcessorError> {
+        let Some(mut htif) = self.htif else {
+            return Ok(());
+        };
+
+        if matches!(slot, DeviceSlot::Memory(_)) && htif.is_tohost(address) {
+            let (slot, tohost) = self.find(htif.tohost, 8)?;
+            let memory = self.device_mut(slot);
+            let value = (memory.read32(tohost + 4)? as u64) << 32 | memory.read32(tohost)? as u64;
+
+            if htif.write_tohost(value) {
+                memory.write32(tohost, 0)?;
+                memory.write32(tohost + 4, 0)?;
+            }
+            self.htif = Some(htif);
         }
+
+        Ok(())
+    }
+
+    pub fn load8(&mut self, start_address: u32, data: Vec<u8>) -> Result<(), ProcessorError> {
+        let (slot, offset) = self.find(start_address, data.len() as u32)?;
+
+        self.device_mut(slot).write_bytes(offset, &data)
+    }
+
+    pub fn read8(&mut self, address: u32) -> Result<u8, ProcessorError> {
+        let (slot, offset) = self.find(address, 1)?;
+        self.device_mut(slot).read8(offset)
+    }
+
+    pub fn read16(&mut self, address: u32) -> Result<u16, ProcessorError> {
+        let (slot, offset) = self.find(address, 2)?;
+        self.device_mut(slot).read16(offset)
+    }
+
+    pub fn read32(&mut self, address: u32) -> Result<u32, ProcessorError> {
+        let (slot, offset) = self.find(address, 4)?;
+        self.device_mut(slot).read32(offset)
+    }
+
+    pub fn write8(&mut self, address: u32, value: u8) -> Result<(), ProcessorError> {
+        let (slot, offset) = self.find(address, 1)?;
+        self.invalidate_reservation(address);
+        self.device_mut(slot).write8(offset, value)?;
+        self.notify_htif(slot, address)
     }
 
     pub fn write16(&mut self, address: u32, value: u16) -> Result<(), ProcessorError> {
-        if address >= DRAM_BASE {
-            self.dram.write16(address - DRAM_BASE, value)
-        } else {
-            Err(BusError::new(BusErrorType::AddressOutOfBounds))
-        }
+        let (slot, offset) = self.find(address, 2)?;
+        self.invalidate_reservation(address);
+        self.device_mut(slot).write16(offset, value)?;
+        self.notify_htif(slot, address)
     }
 
     pub fn write32(&mut self, address: u32, value: u32) -> Result<(), ProcessorError> {
-        if address >= DRAM_BASE {
-            self.dram.write32(address - DRAM_BASE, value)
-        } else {
-            Err(BusError::new(BusErrorType::AddressOutOfBounds))
+        let (slot, offset) = self.find(address, 4)?;
+        self.invalidate_reservation(address);
+        self.device_mut(slot).write32(offset, value)?;
+        self.notify_htif(slot, address)
+    }
+
+    // デバッガからの読み書き。デバイスのレジスタは読むだけで状態が変わることがあるので、メモリだけを扱う
+    // ゲストのストアとは違い、LR.Wの予約やHTIFには影響しない
+    // addressがメモリ領域に割り当てられているか。デバイスにはアクセスしない
+    pub fn is_memory(&self, address: u32) -> bool {
+        matches!(self.find(address, 1), Ok((DeviceSlot::Memory(_), _)))
+    }
+
+    pub fn debug_read8(&self, address: u32) -> Result<u8, ProcessorError> {
+        match self.find(address, 1)? {
+            (DeviceSlot::Memory(index), offset) => self.memory[index].read8(offset),
+            _ => Err(BusError::new(BusErrorType::DebugAccessToDevice(address))),
+        }
+    }
+
+    pub fn debug_write8(&mut self, address: u32, value: u8) -> Result<(), ProcessorError> {
+        match self.find(address, 1)? {
+            (DeviceSlot::Memory(index), offset) => self.memory[index].write8(offset, value),
+            _ => Err(BusError::new(BusErrorType::DebugAccessToDevice(address))),
+        }
+    }
+
+    pub fn load_reserved(&mut self, address: u32) -> Result<u32, ProcessorError> {
+        let value = self.read32(address)?;
+        self.reservation = Some(address);
+
+        Ok(value)
+    }
+
+    // 予約が残っていれば書き込んで成功(true)を返す。予約は成否に関わらず解除する
+    pub fn store_conditional(&mut self, address: u32, value: u32) -> Result<bool, ProcessorError> {
+        let reserved = self.reservation == Some(address);
+        self.reservation = None;
+
+        if reserved {
+            self.write32(address, value)?;
+        }
+
+        Ok(reserved)
+    }
+
+    // AMO命令用に読み出しと書き込みを一度に行い、書き込み前の値を返す
+    pub fn amo32(
+        &mut self,
+        address: u32,
+        operation: impl FnOnce(u32) -> u32,
+    ) -> Result<u32, ProcessorError> {
+        let value = self.read32(address)?;
+        self.write32(address, operation(value))?;
+
+        Ok(value)
+    }
+
+    // 予約されたワードへのストアがあればSC.Wを失敗させる
+    fn invalidate_reservation(&mut self, address: u32) {
+        if self
+            .reservation
+            .is_some_and(|reserved| reserved & !3 == address & !3)
+        {
+            self.reservation = None;
         }
     }
 }
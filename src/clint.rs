@@ -0,0 +1,120 @@
+use crate::{device::Device, processor::ProcessorError, timer::Timer};
+
+// Core Local Interruptor(CLINT)
+// msip/mtimecmp/mtimeを持ち、マシンタイマー割り込みとソフトウェア割り込みを発生させる
+pub const CLINT_BASE: u32 = 0x0200_0000;
+pub const CLINT_SIZE: u32 = 0x1_0000;
+
+const MSIP: u32 = 0x0000;
+const MTIMECMP: u32 = 0x4000;
+const MTIMECMPH: u32 = 0x4004;
+const MTIME: u32 = 0xbff8;
+const MTIMEH: u32 = 0xbffc;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Clint {
+    pub timer: Timer,
+    msip: bool,
+    mtimecmp: u64,
+}
+
+impl Default for Clint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clint {
+    pub fn new() -> Self {
+        Self {
+            timer: Timer::default(),
+            msip: false,
+            // リセット直後にタイマー割り込みが起きないよう最大値にしておく
+            mtimecmp: u64::MAX,
+        }
+    }
+
+    pub fn software_interrupt(&self) -> bool {
+        self.msip
+    }
+
+    pub fn timer_interrupt(&self) -> bool {
+        self.timer.mtime() >= self.mtimecmp
+    }
+
+    // offsetはCLINT_BASEからの4byte境界のオフセット。存在しないレジスタは0を読む
+    pub fn read32(&self, offset: u32) -> u32 {
+        match offset {
+            MSIP => self.msip as u32,
+            MTIMECMP => self.mtimecmp as u32,
+            MTIMECMPH => (self.mtimecmp >> 32) as u32,
+            MTIME => self.timer.mtime() as u32,
+            MTIMEH => (self.timer.mtime() >> 32) as u32,
+            _ => 0,
+        }
+    }
+
+    pub fn write32(&mut self, offset: u32, value: u32) {
+        match offset {
+            MSIP => self.msip = value & 1 != 0,
+            MTIMECMP => self.mtimecmp = self.mtimecmp & !0xffff_ffff | value as u64,
+            MTIMECMPH => self.mtimecmp = self.mtimecmp & 0xffff_ffff | (value as u64) << 32,
+            MTIME => {
+                let mtime = self.timer.mtime() & !0xffff_ffff | value as u64;
+                self.timer.set_mtime(mtime)
+            }
+            MTIMEH => {
+                let mtime = self.timer.mtime() & 0xffff_ffff | (value as u64) << 32;
+                self.timer.set_mtime(mtime)
+            }
+            _ => (),
+        }
+    }
+}
+
+// レジスタは32bit単位なので、それより狭いアクセスはワードの一部として扱う
+impl Device for Clint {
+    fn read8(&mut self, offset: u32) -> Result<u8, ProcessorError> {
+        Ok(self.read_part(offset) as u8)
+    }
+
+    fn read16(&mut self, offset: u32) -> Result<u16, ProcessorError> {
+        Ok(self.read_part(offset) as u16)
+    }
+
+    fn read32(&mut self, offset: u32) -> Result<u32, ProcessorError> {
+        Ok(self.read_part(offset))
+    }
+
+    fn write8(&mut self, offset: u32, value: u8) -> Result<(), ProcessorError> {
+        self.write_part(offset, value as u32, 0xff);
+        Ok(())
+    }
+
+    fn write16(&mut self, offset: u32, value: u16) -> Result<(), ProcessorError> {
+        self.write_part(offset, value as u32, 0xffff);
+        Ok(())
+    }
+
+    fn write32(&mut self, offset: u32, value: u32) -> Result<(), ProcessorError> {
+        self.write_part(offset, value, 0xffff_ffff);
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        self.timer.tick();
+    }
+}
+
+impl Clint {
+    fn read_part(&self, offset: u32) -> u32 {
+        Clint::read32(self, offset & !3) >> ((offset & 3) * 8)
+    }
+
+    fn write_part(&mut self, offset: u32, value: u32, mask: u32) {
+        let shift = (offset & 3) * 8;
+        let word = Clint::read32(self, offset & !3);
+        let word = word & !(mask << shift) | (value & mask) << shift;
+        Clint::write32(self, offset & !3, word);
+    }
+}
@@ -1,12 +1,16 @@
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
+use std::rc::Rc;
 
 use thiserror::Error;
 
 use crate::bus::Bus;
+use crate::elf::{Elf, ElfError, PT_LOAD};
+use crate::htif::Htif;
 use crate::processor::Processor;
 use crate::processor::{ProcessorError, ProcessorResult};
+use crate::symbol::SymbolTable;
 
 #[derive(Debug, Error)]
 pub enum LoadError {
@@ -15,6 +19,22 @@ pub enum LoadError {
 
     #[error("File read error")]
     FileReadError(std::io::Error),
+
+    #[error("ELF parse error: {0}")]
+    ElfParseError(ElfError),
+
+    #[error("Segment load error at 0x{0:0>8x}")]
+    SegmentLoadError(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunResult {
+    // トラップハンドラが無い状態でECALLを実行した
+    Ecall,
+    // HTIFのtohost経由で終了した。0ならpass、それ以外は失敗したテスト番号
+    Exit(u32),
+    // 指定したステップ数を実行しても終了しなかった
+    StepLimit,
 }
 
 pub struct Computer<P>
@@ -23,35 +43,138 @@ where
 {
     processor: P,
     bus: Bus,
+    symbols: Rc<SymbolTable>,
 }
 
 impl<P> Computer<P>
 where
     P: Processor,
 {
-    pub fn new(processor: P, bus: Bus) -> Self {
-        Self { processor, bus }
+    pub fn new(mut processor: P, bus: Bus) -> Self {
+        processor.reset(bus.config());
+
+        Self {
+            processor,
+            bus,
+            symbols: Rc::new(SymbolTable::new()),
+        }
+    }
+
+    pub fn processor(&self) -> &P {
+        &self.processor
+    }
+
+    // 外部からレジスタなどを操作する
+    pub fn processor_mut(&mut self) -> &mut P {
+        &mut self.processor
     }
 
+    pub fn bus(&self) -> &Bus {
+        &self.bus
+    }
+
+    // 外部からデバイスを操作する(割り込み線を上げるなど)
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    // ELFファイルであればセグメントを配置し、それ以外はstart_addressにそのままコピーする
     pub fn load_from_file(&mut self, start_address: u32, path: &Path) -> Result<(), LoadError> {
-        let mut program_file = File::open(path).map_err(|e| LoadError::FileOpenError(e))?;
+        let mut program_file = File::open(path).map_err(LoadError::FileOpenError)?;
         let mut program_data = Vec::new();
-        program_file.read_to_end(&mut program_data).map_err(|e| LoadError::FileReadError(e))?;
+        program_file
+            .read_to_end(&mut program_data)
+            .map_err(LoadError::FileReadError)?;
+
+        if Elf::is_elf(&program_data) {
+            self.load_elf(&program_data)
+        } else {
+            self.bus
+                .load8(start_address, program_data)
+                .map_err(|_| LoadError::SegmentLoadError(start_address))?;
+            self.processor.set_pc(start_address);
 
-        let _ = self.bus.load8(start_address, program_data); // TODO
+            Ok(())
+        }
+    }
+
+    pub fn load_elf(&mut self, data: &[u8]) -> Result<(), LoadError> {
+        let elf = Elf::parse(data).map_err(LoadError::ElfParseError)?;
+
+        for header in elf.program_headers.iter().filter(|h| h.p_type == PT_LOAD) {
+            let segment = elf
+                .segment_data(header)
+                .map_err(LoadError::ElfParseError)?;
+            self.bus
+                .load8(header.paddr, segment.to_vec())
+                .map_err(|_| LoadError::SegmentLoadError(header.paddr))?;
+
+            // memszがfileszより大きい部分は.bssなのでゼロで埋める
+            if header.memsz > header.filesz {
+                let bss_address = header
+                    .paddr
+                    .checked_add(header.filesz)
+                    .ok_or(LoadError::SegmentLoadError(header.paddr))?;
+                self.bus
+                    .load8(bss_address, vec![0; (header.memsz - header.filesz) as usize])
+                    .map_err(|_| LoadError::SegmentLoadError(bss_address))?;
+            }
+        }
+
+        let elf_symbols = elf.symbols().map_err(LoadError::ElfParseError)?;
+        self.symbols = Rc::new(SymbolTable::from_elf_symbols(&elf_symbols));
+        self.processor.set_symbols(Rc::clone(&self.symbols));
+
+        // riscv-testsはtohostシンボルへの書き込みで結果を返す
+        self.bus.htif = self
+            .symbols
+            .address_of("tohost")
+            .map(|tohost| Htif::new(tohost, self.symbols.address_of("fromhost")));
+
+        self.processor.set_pc(elf.entry);
 
         Ok(())
     }
 
-    pub fn run(&mut self) -> Result<(), ProcessorError> {
+    pub fn run(&mut self) -> Result<RunResult, ProcessorError> {
+        self.run_with_step_limit(None)
+    }
+
+    pub fn run_with_step_limit(
+        &mut self,
+        step_limit: Option<u64>,
+    ) -> Result<RunResult, ProcessorError> {
+        let mut steps = 0;
+
         loop {
-            match self.processor.increment(&mut self.bus)? {
-                ProcessorResult::OK => (),
-                ProcessorResult::ECALL => break,
-            };
+            if step_limit.is_some_and(|limit| steps >= limit) {
+                return Ok(RunResult::StepLimit);
+            }
+            steps += 1;
+
+            if let Some(result) = self.step()? {
+                return Ok(result);
+            }
             // thread::sleep(Duration::from_millis(100));
         }
+    }
 
-        Ok(())
+    // 1ステップ実行する。プログラムが終了すればその結果を返す
+    pub fn step(&mut self) -> Result<Option<RunResult>, ProcessorError> {
+        let result = self.processor.increment(&mut self.bus)?;
+
+        if let Some(exit_code) = self.bus.htif_exit_code() {
+            return Ok(Some(RunResult::Exit(exit_code)));
+        }
+
+        if result == ProcessorResult::ECALL {
+            return Ok(Some(RunResult::Ecall));
+        }
+
+        Ok(None)
     }
 }
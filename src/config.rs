@@ -0,0 +1,171 @@
+use thiserror::Error;
+
+use crate::{
+    bus::DRAM_BASE,
+    clint::{CLINT_BASE, CLINT_SIZE},
+    plic::{PLIC_BASE, PLIC_SIZE},
+    uart::{UART_BASE, UART_SIZE},
+};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ConfigError {
+    #[error("Memory region 0x{base:0>8x} (size 0x{size:x}) is empty or exceeds the address space")]
+    InvalidRegion { base: u32, size: u32 },
+
+    #[error("Memory region 0x{base:0>8x} (size 0x{size:x}) overlaps another region or device")]
+    OverlappingRegion { base: u32, size: u32 },
+}
+
+pub const DEFAULT_DRAM_SIZE: u32 = 1024 * 1024; // 1MB
+
+// 組み込みデバイスの領域。メモリはここに置けない
+const DEVICE_REGIONS: [(u32, u32); 3] = [
+    (CLINT_BASE, CLINT_SIZE),
+    (PLIC_BASE, PLIC_SIZE),
+    (UART_BASE, UART_SIZE),
+];
+
+// メモリの実装。Flatは領域全体を一度に確保し、Sparseは4KiBページ単位で書き込まれたときに確保する
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MemoryBackend {
+    #[default]
+    Flat,
+    Sparse,
+}
+
+// 境界に揃っていないロード/ストアの扱い
+// Emulateは1byteずつのアクセスに分けて実行し、Trapはアドレス不整列例外を起こす
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MisalignedAccess {
+    #[default]
+    Emulate,
+    Trap,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub base: u32,
+    pub size: u32,
+}
+
+impl MemoryRegion {
+    fn end(&self) -> u64 {
+        self.base as u64 + self.size as u64
+    }
+
+    fn overlaps(&self, base: u32, size: u32) -> bool {
+        (base as u64) < self.end() && (self.base as u64) < base as u64 + size as u64
+    }
+}
+
+// マシン構成。先頭のメモリ領域がメインメモリで、リセット時のpcとspはここから決める
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachineConfig {
+    memory: Vec<MemoryRegion>,
+    memory_backend: MemoryBackend,
+    misaligned_access: MisalignedAccess,
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        Self {
+            memory: vec![MemoryRegion {
+                base: DRAM_BASE,
+                size: DEFAULT_DRAM_SIZE,
+            }],
+            memory_backend: MemoryBackend::default(),
+            misaligned_access: MisalignedAccess::default(),
+        }
+    }
+}
+
+impl MachineConfig {
+    pub fn new(dram_base: u32, dram_size: u32) -> Result<Self, ConfigError> {
+        let mut config = Self {
+            memory: Vec::new(),
+            memory_backend: MemoryBackend::default(),
+            misaligned_access: MisalignedAccess::default(),
+        };
+        config.add_memory(dram_base, dram_size)?;
+
+        Ok(config)
+    }
+
+    // メインメモリ以外のメモリ領域を追加する
+    pub fn add_memory(&mut self, base: u32, size: u32) -> Result<(), ConfigError> {
+        let region = MemoryRegion { base, size };
+        if size == 0 || region.end() > 1 << 32 {
+            return Err(ConfigError::InvalidRegion { base, size });
+        }
+
+        let devices = DEVICE_REGIONS.map(|(base, size)| MemoryRegion { base, size });
+        if self
+            .memory
+            .iter()
+            .chain(devices.iter())
+            .any(|other| other.overlaps(base, size))
+        {
+            return Err(ConfigError::OverlappingRegion { base, size });
+        }
+
+        self.memory.push(region);
+
+        Ok(())
+    }
+
+    // メモリにもデバイスにも使われていないアドレスを全てメモリ領域にする
+    // デバイスの領域はデバイスのまま残るので、Sparseと組み合わせて32bitの空間全体をメモリとして使う
+    pub fn fill_address_space(&mut self) {
+        let mut used: Vec<MemoryRegion> = DEVICE_REGIONS
+            .map(|(base, size)| MemoryRegion { base, size })
+            .into_iter()
+            .chain(self.memory.iter().copied())
+            .collect();
+        used.sort_by_key(|region| region.base);
+
+        // 使われている領域の間と、最後の領域から空間の末尾までを埋める
+        let mut next = 0u64;
+        for (base, end) in used
+            .iter()
+            .map(|region| (region.base as u64, region.end()))
+            .chain([(1 << 32, 1 << 32)])
+        {
+            if next < base {
+                self.memory.push(MemoryRegion {
+                    base: next as u32,
+                    size: (base - next) as u32,
+                });
+            }
+            next = next.max(end);
+        }
+    }
+
+    pub fn memory(&self) -> &[MemoryRegion] {
+        &self.memory
+    }
+
+    pub fn memory_backend(&self) -> MemoryBackend {
+        self.memory_backend
+    }
+
+    pub fn set_memory_backend(&mut self, backend: MemoryBackend) {
+        self.memory_backend = backend;
+    }
+
+    pub fn misaligned_access(&self) -> MisalignedAccess {
+        self.misaligned_access
+    }
+
+    pub fn set_misaligned_access(&mut self, policy: MisalignedAccess) {
+        self.misaligned_access = policy;
+    }
+
+    pub fn reset_vector(&self) -> u32 {
+        self.memory[0].base
+    }
+
+    // スタックポインターはメインメモリの末尾から始める
+    pub fn initial_stack_pointer(&self) -> u32 {
+        self.memory[0].base.wrapping_add(self.memory[0].size)
+    }
+}
@@ -0,0 +1,75 @@
+use std::fmt::{Debug, Display};
+
+use crate::processor::{ProcessorError, ProcessorErrorTrait};
+
+pub enum DeviceErrorType {
+    // デバイスが対応していない幅やアライメントでのアクセス
+    UnsupportedAccess { offset: u32, width: u32 },
+}
+
+pub struct DeviceError {
+    pub error_type: DeviceErrorType,
+}
+
+impl DeviceError {
+    pub fn new(error_type: DeviceErrorType) -> Box<Self> {
+        Box::new(DeviceError { error_type })
+    }
+}
+
+impl ProcessorErrorTrait for DeviceError {}
+
+impl Display for DeviceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.error_type {
+            DeviceErrorType::UnsupportedAccess { offset, width } => write!(
+                f,
+                "Unsupported {}-byte device access at offset 0x{:x}",
+                width, offset
+            ),
+        }
+    }
+}
+
+// Busのアドレス空間に割り当てるメモリマップトデバイス
+// offsetは割り当てた領域の先頭からのオフセット。16/32bitアクセスは既定ではリトルエンディアンで1byteずつ行う
+pub trait Device: Debug {
+    fn read8(&mut self, offset: u32) -> Result<u8, ProcessorError>;
+
+    fn write8(&mut self, offset: u32, value: u8) -> Result<(), ProcessorError>;
+
+    fn read16(&mut self, offset: u32) -> Result<u16, ProcessorError> {
+        Ok((self.read8(offset + 1)? as u16) << 8 | self.read8(offset)? as u16)
+    }
+
+    fn read32(&mut self, offset: u32) -> Result<u32, ProcessorError> {
+        Ok((self.read16(offset + 2)? as u32) << 16 | self.read16(offset)? as u32)
+    }
+
+    fn write16(&mut self, offset: u32, value: u16) -> Result<(), ProcessorError> {
+        self.write8(offset, value as u8)?;
+        self.write8(offset + 1, (value >> 8) as u8)
+    }
+
+    fn write32(&mut self, offset: u32, value: u32) -> Result<(), ProcessorError> {
+        self.write16(offset, value as u16)?;
+        self.write16(offset + 2, (value >> 16) as u16)
+    }
+
+    // プログラムのロードなどでまとめて書き込む
+    fn write_bytes(&mut self, offset: u32, data: &[u8]) -> Result<(), ProcessorError> {
+        for (count, &value) in data.iter().enumerate() {
+            self.write8(offset + count as u32, value)?;
+        }
+
+        Ok(())
+    }
+
+    // 1サイクルごとに呼ばれる
+    fn tick(&mut self) {}
+
+    // 割り込み線のレベル。割り込みソースを指定して登録したときだけPLICに繋がる
+    fn is_interrupting(&self) -> bool {
+        false
+    }
+}
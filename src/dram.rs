@@ -1,4 +1,7 @@
-use crate::processor::{ProcessorError, ProcessorErrorTrait};
+use crate::{
+    device::Device,
+    processor::{ProcessorError, ProcessorErrorTrait},
+};
 use std::fmt::Display;
 
 pub enum DramErrorType {
@@ -25,105 +28,115 @@ impl Display for DramError {
     }
 }
 
-pub const DRAM_SIZE: u32 = 1024 * 1024; // 1MB
-
 #[derive(Debug, Clone)]
 pub struct Dram {
     dram: Vec<u8>,
 }
 
 impl Dram {
-    pub fn new() -> Self {
+    // 大きなメモリを使うときは確保が遅延されるMemoryBackend::Sparseを選ぶ
+    pub fn new(size: u32) -> Self {
         Self {
-            dram: vec![0; DRAM_SIZE as usize],
+            dram: vec![0; size as usize],
         }
     }
 
-    pub fn load8(&mut self, start_address: u32, data: Vec<u8>) -> Result<(), ProcessorError> {
-        if start_address + (data.len() as u32) < DRAM_SIZE {
-            for (count, mem) in self
-                .dram
-                .iter_mut()
-                .skip(start_address as usize)
-                .take(data.len())
-                .enumerate()
-            {
-                *mem = data[count];
-            }
+    pub fn size(&self) -> u32 {
+        self.dram.len() as u32
+    }
+
+    fn check(&self, address: u32, length: usize) -> Result<(), ProcessorError> {
+        if address as usize + length <= self.dram.len() {
             Ok(())
         } else {
             Err(DramError::new(DramErrorType::AddressOutOfBounds))
         }
     }
 
+    pub fn load8(&mut self, start_address: u32, data: Vec<u8>) -> Result<(), ProcessorError> {
+        Device::write_bytes(self, start_address, &data)
+    }
+
     pub fn read8(&self, address: u32) -> Result<u8, ProcessorError> {
-        if address < DRAM_SIZE {
-            Ok(self.dram[address as usize])
-        } else {
-            Err(DramError::new(DramErrorType::AddressOutOfBounds))
-        }
+        self.check(address, 1)?;
+        Ok(self.dram[address as usize])
     }
 
     pub fn read16(&self, address: u32) -> Result<u16, ProcessorError> {
-        if address < DRAM_SIZE {
-            Ok(
-                (self.dram[address as usize + 1] as u16) << 8
-                    | (self.dram[address as usize] as u16),
-            )
-        } else {
-            Err(DramError::new(DramErrorType::AddressOutOfBounds))
-        }
+        self.check(address, 2)?;
+        Ok((self.dram[address as usize + 1] as u16) << 8 | (self.dram[address as usize] as u16))
     }
 
     pub fn read32(&self, address: u32) -> Result<u32, ProcessorError> {
-        if address < DRAM_SIZE {
-            Ok((self.dram[address as usize + 3] as u32) << 24
-                | (self.dram[address as usize + 2] as u32) << 16
-                | (self.dram[address as usize + 1] as u32) << 8
-                | (self.dram[address as usize] as u32))
-        } else {
-            Err(DramError::new(DramErrorType::AddressOutOfBounds))
-        }
+        self.check(address, 4)?;
+        Ok((self.dram[address as usize + 3] as u32) << 24
+            | (self.dram[address as usize + 2] as u32) << 16
+            | (self.dram[address as usize + 1] as u32) << 8
+            | (self.dram[address as usize] as u32))
     }
 
     pub fn write8(&mut self, address: u32, value: u8) -> Result<(), ProcessorError> {
-        if address < DRAM_SIZE {
-            self.dram[address as usize] = value;
-            Ok(())
-        } else {
-            Err(DramError::new(DramErrorType::AddressOutOfBounds))
-        }
+        self.check(address, 1)?;
+        self.dram[address as usize] = value;
+        Ok(())
     }
 
     pub fn write16(&mut self, address: u32, value: u16) -> Result<(), ProcessorError> {
-        if address < DRAM_SIZE {
-            self.dram[address as usize + 1] = (value >> 8) as u8;
-            self.dram[address as usize] = value as u8;
-            Ok(())
-        } else {
-            Err(DramError::new(DramErrorType::AddressOutOfBounds))
-        }
+        self.check(address, 2)?;
+        self.dram[address as usize + 1] = (value >> 8) as u8;
+        self.dram[address as usize] = value as u8;
+        Ok(())
     }
 
     pub fn write32(&mut self, address: u32, value: u32) -> Result<(), ProcessorError> {
-        if address < DRAM_SIZE {
-            self.dram[address as usize + 3] = (value >> 24) as u8;
-            self.dram[address as usize + 2] = ((value & 0x00FF_0000) >> 16) as u8;
-            self.dram[address as usize + 1] = ((value & 0x0000_FF00) >> 8) as u8;
-            self.dram[address as usize] = value as u8;
+        self.check(address, 4)?;
+        self.dram[address as usize + 3] = (value >> 24) as u8;
+        self.dram[address as usize + 2] = ((value & 0x00FF_0000) >> 16) as u8;
+        self.dram[address as usize + 1] = ((value & 0x0000_FF00) >> 8) as u8;
+        self.dram[address as usize] = value as u8;
 
-            Ok(())
-        } else {
-            Err(DramError::new(DramErrorType::AddressOutOfBounds))
-        }
+        Ok(())
+    }
+}
+
+impl Device for Dram {
+    fn read8(&mut self, offset: u32) -> Result<u8, ProcessorError> {
+        Dram::read8(self, offset)
+    }
+
+    fn read16(&mut self, offset: u32) -> Result<u16, ProcessorError> {
+        Dram::read16(self, offset)
+    }
+
+    fn read32(&mut self, offset: u32) -> Result<u32, ProcessorError> {
+        Dram::read32(self, offset)
+    }
+
+    fn write8(&mut self, offset: u32, value: u8) -> Result<(), ProcessorError> {
+        Dram::write8(self, offset, value)
+    }
+
+    fn write16(&mut self, offset: u32, value: u16) -> Result<(), ProcessorError> {
+        Dram::write16(self, offset, value)
+    }
+
+    fn write32(&mut self, offset: u32, value: u32) -> Result<(), ProcessorError> {
+        Dram::write32(self, offset, value)
+    }
+
+    fn write_bytes(&mut self, offset: u32, data: &[u8]) -> Result<(), ProcessorError> {
+        self.check(offset, data.len())?;
+        self.dram[offset as usize..offset as usize + data.len()].copy_from_slice(data);
+
+        Ok(())
     }
 }
 
 impl Display for Dram {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut str = String::new();
-        for i in 0..DRAM_SIZE {
-            str += &format!("{:0>2x} ", self.dram[i as usize]).to_string();
+        for (i, byte) in self.dram.iter().enumerate() {
+            str += &format!("{:0>2x} ", byte).to_string();
             if i % 8 == 7 {
                 str += " ";
             }
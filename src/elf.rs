@@ -0,0 +1,267 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ElfError {
+    #[error("Not an ELF file")]
+    InvalidMagic,
+
+    #[error("Not a 32-bit ELF file")]
+    UnsupportedClass,
+
+    #[error("Not a little-endian ELF file")]
+    UnsupportedEndian,
+
+    #[error("Not a RISC-V ELF file")]
+    UnsupportedMachine,
+
+    #[error("ELF file is truncated")]
+    Truncated,
+
+    #[error("No executable section")]
+    NoExecutableSection,
+
+    #[error("Invalid symbol table entry size: {0}")]
+    InvalidSymbolEntrySize(u32),
+}
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+const EM_RISCV: u16 = 243;
+
+pub const PT_LOAD: u32 = 1;
+
+pub const SHT_PROGBITS: u32 = 1;
+pub const SHT_SYMTAB: u32 = 2;
+
+pub const SHF_EXECINSTR: u32 = 0x4;
+
+pub const STT_NOTYPE: u8 = 0;
+pub const STT_OBJECT: u8 = 1;
+pub const STT_FUNC: u8 = 2;
+
+pub const STB_GLOBAL: u8 = 1;
+
+const SYMBOL_ENTRY_SIZE: u32 = 16;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ProgramHeader {
+    pub p_type: u32,
+    pub offset: u32,
+    pub vaddr: u32,
+    pub paddr: u32,
+    pub filesz: u32,
+    pub memsz: u32,
+    pub flags: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SectionHeader {
+    pub name: u32,
+    pub sh_type: u32,
+    pub flags: u32,
+    pub addr: u32,
+    pub offset: u32,
+    pub size: u32,
+    pub link: u32,
+    pub entsize: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ElfSymbol {
+    pub name: String,
+    pub value: u32,
+    pub size: u32,
+    pub info: u8,
+    pub shndx: u16,
+}
+
+impl ElfSymbol {
+    pub fn symbol_type(&self) -> u8 {
+        self.info & 0xf
+    }
+
+    pub fn binding(&self) -> u8 {
+        self.info >> 4
+    }
+}
+
+pub struct Elf<'a> {
+    data: &'a [u8],
+    pub entry: u32,
+    pub program_headers: Vec<ProgramHeader>,
+    pub section_headers: Vec<SectionHeader>,
+    // セクション名の文字列テーブル(.shstrtab)のインデックス
+    pub shstrndx: u16,
+}
+
+impl<'a> Elf<'a> {
+    pub fn is_elf(data: &[u8]) -> bool {
+        data.starts_with(&ELF_MAGIC)
+    }
+
+    pub fn parse(data: &'a [u8]) -> Result<Self, ElfError> {
+        if !Self::is_elf(data) {
+            return Err(ElfError::InvalidMagic);
+        }
+        if data.len() < 52 {
+            return Err(ElfError::Truncated);
+        }
+        if data[4] != ELFCLASS32 {
+            return Err(ElfError::UnsupportedClass);
+        }
+        if data[5] != ELFDATA2LSB {
+            return Err(ElfError::UnsupportedEndian);
+        }
+        if read16(data, 18)? != EM_RISCV {
+            return Err(ElfError::UnsupportedMachine);
+        }
+
+        let entry = read32(data, 24)?;
+        let phoff = read32(data, 28)?;
+        let shoff = read32(data, 32)?;
+        let phentsize = read16(data, 42)? as u32;
+        let phnum = read16(data, 44)? as u32;
+        let shentsize = read16(data, 46)? as u32;
+        let shnum = read16(data, 48)? as u32;
+        let shstrndx = read16(data, 50)?;
+
+        let mut program_headers = Vec::new();
+        for i in 0..phnum {
+            let base = header_offset(phoff, i, phentsize)?;
+            program_headers.push(ProgramHeader {
+                p_type: read32(data, base)?,
+                offset: read32(data, base + 4)?,
+                vaddr: read32(data, base + 8)?,
+                paddr: read32(data, base + 12)?,
+                filesz: read32(data, base + 16)?,
+                memsz: read32(data, base + 20)?,
+                flags: read32(data, base + 24)?,
+            });
+        }
+
+        let mut section_headers = Vec::new();
+        for i in 0..shnum {
+            let base = header_offset(shoff, i, shentsize)?;
+            section_headers.push(SectionHeader {
+                name: read32(data, base)?,
+                sh_type: read32(data, base + 4)?,
+                flags: read32(data, base + 8)?,
+                addr: read32(data, base + 12)?,
+                offset: read32(data, base + 16)?,
+                size: read32(data, base + 20)?,
+                link: read32(data, base + 24)?,
+                entsize: read32(data, base + 36)?,
+            });
+        }
+
+        Ok(Self {
+            data,
+            entry,
+            program_headers,
+            section_headers,
+            shstrndx,
+        })
+    }
+
+    // PT_LOADセグメントのうちファイルに含まれる部分(filesz)を返す
+    pub fn segment_data(&self, header: &ProgramHeader) -> Result<&'a [u8], ElfError> {
+        slice(self.data, header.offset, header.filesz)
+    }
+
+    pub fn section_data(&self, header: &SectionHeader) -> Result<&'a [u8], ElfError> {
+        slice(self.data, header.offset, header.size)
+    }
+
+    // .shstrtabからセクション名を読む
+    pub fn section_name(&self, header: &SectionHeader) -> Result<String, ElfError> {
+        let shstrtab = self
+            .section_headers
+            .get(self.shstrndx as usize)
+            .ok_or(ElfError::Truncated)?;
+        let name = self
+            .section_data(shstrtab)?
+            .get(header.name as usize..)
+            .and_then(|s| s.split(|&c| c == 0).next())
+            .ok_or(ElfError::Truncated)?;
+
+        Ok(String::from_utf8_lossy(name).into_owned())
+    }
+
+    // .textや.text.initなど、命令が入っているセクション
+    pub fn executable_sections(&self) -> impl Iterator<Item = &SectionHeader> {
+        self.section_headers
+            .iter()
+            .filter(|h| h.sh_type == SHT_PROGBITS && h.flags & SHF_EXECINSTR != 0)
+    }
+
+    // .symtabとそのsh_linkが指す.strtabからシンボル一覧を読み出す
+    pub fn symbols(&self) -> Result<Vec<ElfSymbol>, ElfError> {
+        let mut symbols = Vec::new();
+
+        for symtab in self.section_headers.iter().filter(|h| h.sh_type == SHT_SYMTAB) {
+            let strtab = self
+                .section_headers
+                .get(symtab.link as usize)
+                .ok_or(ElfError::Truncated)?;
+            let strtab_data = self.section_data(strtab)?;
+            let symtab_data = self.section_data(symtab)?;
+
+            let entsize = match symtab.entsize {
+                0 => SYMBOL_ENTRY_SIZE,
+                // Elf32_Symより短いエントリは読めない
+                entsize if entsize < SYMBOL_ENTRY_SIZE => {
+                    return Err(ElfError::InvalidSymbolEntrySize(entsize))
+                }
+                entsize => entsize,
+            };
+
+            for entry in symtab_data.chunks_exact(entsize as usize) {
+                let name_offset = read32(entry, 0)? as usize;
+                let name = strtab_data
+                    .get(name_offset..)
+                    .and_then(|s| s.split(|&c| c == 0).next())
+                    .ok_or(ElfError::Truncated)?;
+
+                symbols.push(ElfSymbol {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    value: read32(entry, 4)?,
+                    size: read32(entry, 8)?,
+                    info: entry[12],
+                    shndx: read16(entry, 14)?,
+                });
+            }
+        }
+
+        Ok(symbols)
+    }
+}
+
+// ヘッダテーブルのindex番目の先頭。32bitに収まらなければファイルの外を指している
+fn header_offset(table: u32, index: u32, entsize: u32) -> Result<usize, ElfError> {
+    index
+        .checked_mul(entsize)
+        .and_then(|offset| table.checked_add(offset))
+        .map(|offset| offset as usize)
+        .ok_or(ElfError::Truncated)
+}
+
+fn slice(data: &[u8], offset: u32, size: u32) -> Result<&[u8], ElfError> {
+    let start = offset as usize;
+    let end = start
+        .checked_add(size as usize)
+        .ok_or(ElfError::Truncated)?;
+    data.get(start..end).ok_or(ElfError::Truncated)
+}
+
+fn read16(data: &[u8], offset: usize) -> Result<u16, ElfError> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or(ElfError::Truncated)
+}
+
+fn read32(data: &[u8], offset: usize) -> Result<u32, ElfError> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(ElfError::Truncated)
+}
@@ -0,0 +1,586 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+
+use thiserror::Error;
+
+use crate::computer::{Computer, RunResult};
+use crate::processor::riscv::rv32ui::cs_register::CSR_NAMES;
+use crate::processor::riscv::rv32ui::writeback::WritebackResult;
+use crate::processor::riscv::rv32ui::x_register::abi_name;
+use crate::processor::riscv::rv32ui::RiscVUIProcessor;
+
+// qSupportedで知らせるパケットの最大長
+const PACKET_SIZE: u32 = 0x1000;
+
+#[derive(Debug, Error)]
+pub enum GdbError {
+    #[error("GDB connection error: {0}")]
+    ConnectionError(io::Error),
+}
+
+// レジスタ番号はGDBのRISC-Vの番号付けに合わせる
+// x0-x31の次がpc、CSRは65 + CSR番号
+const PC_REGNUM: u32 = 32;
+const CSR_REGNUM_BASE: u32 = 65;
+
+// continue中にCtrl-Cが届いていないか確認する間隔(ステップ数)
+const INTERRUPT_POLL_INTERVAL: u64 = 1024;
+
+const INTERRUPT: u8 = 0x03;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WatchKind {
+    Write,
+    Read,
+    Access,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Watchpoint {
+    kind: WatchKind,
+    address: u32,
+    length: u32,
+}
+
+impl Watchpoint {
+    fn overlaps(&self, address: u32, width: u32) -> bool {
+        (address as u64) < self.address as u64 + self.length as u64
+            && (self.address as u64) < address as u64 + width as u64
+    }
+
+    // 直前の命令のアクセスが引っかかれば、stop replyに書く種類とアクセスしたアドレスを返す
+    // アドレスはアクセスのうちウォッチしている範囲に入る最初のバイト
+    fn hit(&self, access: &WritebackResult) -> Option<(&'static str, u32)> {
+        let read = access
+            .load
+            .filter(|&(address, width)| self.overlaps(address, width))
+            .map(|(address, _)| address.max(self.address));
+        let write = access
+            .store
+            .filter(|&(address, _, width)| self.overlaps(address, width))
+            .map(|(address, _, _)| address.max(self.address));
+
+        match self.kind {
+            WatchKind::Write => write.map(|address| ("watch", address)),
+            WatchKind::Read => read.map(|address| ("rwatch", address)),
+            WatchKind::Access => read.or(write).map(|address| ("awatch", address)),
+        }
+    }
+}
+
+// 実行を止めた理由
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Stop {
+    Signal(u8),
+    Breakpoint,
+    Watchpoint(&'static str, u32),
+    // ホストに処理を返すエラーはSIGSEGVとして通知し、メッセージはGDBのコンソールに出す
+    Fault(String),
+    Exited(u32),
+}
+
+// GDBのリモートシリアルプロトコル(RSP)でComputerを操作する
+// メモリはMMUを通さず、物理アドレスとしてBusのメモリだけを読み書きする
+pub struct GdbStub<'a> {
+    computer: &'a mut Computer<RiscVUIProcessor>,
+    stream: TcpStream,
+    // 受信して、まだ処理していないバイト
+    received: VecDeque<u8>,
+    closed: bool,
+    // '-'が返ってきたら再送する
+    last_packet: Vec<u8>,
+    breakpoints: BTreeSet<u32>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl<'a> GdbStub<'a> {
+    pub fn new(computer: &'a mut Computer<RiscVUIProcessor>, stream: TcpStream) -> Self {
+        Self {
+            computer,
+            stream,
+            received: VecDeque::new(),
+            closed: false,
+            last_packet: Vec::new(),
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+        }
+    }
+
+    // GDBが切断するか、kill/detachされるまでパケットを処理する
+    pub fn run(&mut self) -> Result<(), GdbError> {
+        self.stream
+            .set_nodelay(true)
+            .map_err(GdbError::ConnectionError)?;
+
+        while let Some(packet) = self.read_packet()? {
+            let packet = String::from_utf8_lossy(&packet).into_owned();
+            match self.handle(&packet)? {
+                Some(response) => self.send_packet(&response)?,
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    // 応答を返す。Noneなら接続を終える
+    fn handle(&mut self, packet: &str) -> Result<Option<String>, GdbError> {
+        let Some(command) = packet.chars().next() else {
+            return Ok(Some(String::new()));
+        };
+        let args = &packet[command.len_utf8()..];
+
+        let response = match command {
+            '?' => format!("S{:02x}", SIGTRAP),
+            'g' => self.read_registers(),
+            'G' => self.write_registers(args),
+            'p' => self.read_register(args),
+            'P' => self.write_register(args),
+            'm' => self.read_memory(args),
+            'M' => self.write_memory(args),
+            's' | 'c' => {
+                if let Some(address) = parse_hex(args) {
+                    self.computer.processor_mut().pc = address;
+                }
+                let stop = if command == 's' {
+                    self.step().unwrap_or(Stop::Signal(SIGTRAP))
+                } else {
+                    self.resume()?
+                };
+                self.stop_reply(stop)?
+            }
+            'Z' | 'z' => self.update_point(command == 'Z', args),
+            // スレッドは1つしか無い
+            'H' | 'T' => "OK".to_string(),
+            'D' => {
+                self.send_packet("OK")?;
+                return Ok(None);
+            }
+            'k' => return Ok(None),
+            'q' => self.query(args),
+            // 対応していないパケットには空の応答を返す
+            _ => String::new(),
+        };
+
+        Ok(Some(response))
+    }
+
+    fn query(&self, args: &str) -> String {
+        if args.starts_with("Supported") {
+            return format!(
+                "PacketSize={:x};qXfer:features:read+;swbreak+;hwbreak+",
+                PACKET_SIZE
+            );
+        }
+        if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+            return read_xfer(&target_xml(), range);
+        }
+
+        match args {
+            "Attached" => "1".to_string(),
+            "C" => "QC1".to_string(),
+            "fThreadInfo" => "m1".to_string(),
+            "sThreadInfo" => "l".to_string(),
+            "Symbol::" => "OK".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    // x0-x31とpc
+    fn read_registers(&self) -> String {
+        (0..=PC_REGNUM)
+            .map(|regnum| encode_register(self.register(regnum).unwrap_or(0)))
+            .collect()
+    }
+
+    fn write_registers(&mut self, args: &str) -> String {
+        let values: Option<Vec<u32>> = (0..=PC_REGNUM as usize)
+            .map(|index| args.get(index * 8..index * 8 + 8).and_then(decode_register))
+            .collect();
+        let Some(values) = values else {
+            return "E01".to_string();
+        };
+
+        for (regnum, value) in values.into_iter().enumerate() {
+            self.set_register(regnum as u32, value);
+        }
+
+        "OK".to_string()
+    }
+
+    fn read_register(&self, args: &str) -> String {
+        parse_hex(args)
+            .and_then(|regnum| self.register(regnum))
+            .map_or_else(|| "E01".to_string(), encode_register)
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let parsed = args
+            .split_once('=')
+            .and_then(|(regnum, value)| Some((parse_hex(regnum)?, decode_register(value)?)));
+
+        match parsed {
+            Some((regnum, value)) if self.set_register(regnum, value) => "OK".to_string(),
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn register(&self, regnum: u32) -> Option<u32> {
+        let processor = self.computer.processor();
+        match regnum {
+            0..PC_REGNUM => Some(processor.xregs.read(regnum)),
+            PC_REGNUM => Some(processor.pc),
+            _ => csr_index(regnum).map(|index| processor.csr.read(index)),
+        }
+    }
+
+    fn set_register(&mut self, regnum: u32, value: u32) -> bool {
+        let processor = self.computer.processor_mut();
+        match regnum {
+            0..PC_REGNUM => processor.xregs.write(regnum, value),
+            PC_REGNUM => processor.pc = value,
+            _ => match csr_index(regnum) {
+                Some(index) => processor.csr.write(index, value),
+                None => return false,
+            },
+        }
+
+        true
+    }
+
+    // m addr,length
+    // 1バイトを2文字で返すので、パケットに収まらない分は読まない。GDBは残りを続けて要求する
+    fn read_memory(&mut self, args: &str) -> String {
+        let Some((address, length)) = parse_address_length(args) else {
+            return "E01".to_string();
+        };
+        let length = length.min(PACKET_SIZE / 2);
+
+        let bus = self.computer.bus();
+        let mut response = String::new();
+        for offset in 0..length {
+            match bus.debug_read8(address.wrapping_add(offset)) {
+                Ok(byte) => response.push_str(&format!("{:0>2x}", byte)),
+                Err(_) => return "E01".to_string(),
+            }
+        }
+
+        response
+    }
+
+    // M addr,length:XX...
+    fn write_memory(&mut self, args: &str) -> String {
+        let parsed = args.split_once(':').and_then(|(range, data)| {
+            let (address, length) = parse_address_length(range)?;
+            let bytes = decode_hex(data)?;
+            (bytes.len() == length as usize).then_some((address, bytes))
+        });
+        let Some((address, bytes)) = parsed else {
+            return "E01".to_string();
+        };
+
+        for (offset, byte) in bytes.into_iter().enumerate() {
+            if self
+                .computer
+                .bus_mut()
+                .debug_write8(address.wrapping_add(offset as u32), byte)
+                .is_err()
+            {
+                return "E01".to_string();
+            }
+        }
+
+        "OK".to_string()
+    }
+
+    // Z0/Z1はブレークポイント、Z2/Z3/Z4は書き込み/読み込み/アクセスのウォッチポイント
+    // ソフトウェアブレークポイントもメモリは書き換えず、pcが一致したら止める
+    fn update_point(&mut self, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let kind = fields.next();
+        let address = fields.next().and_then(parse_hex);
+        let length = fields.next().and_then(parse_hex);
+        let (Some(kind), Some(address), Some(length)) = (kind, address, length) else {
+            return "E01".to_string();
+        };
+
+        let kind = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.breakpoints.insert(address);
+                } else {
+                    self.breakpoints.remove(&address);
+                }
+                return "OK".to_string();
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return String::new(),
+        };
+
+        let watchpoint = Watchpoint {
+            kind,
+            address,
+            length,
+        };
+        if insert {
+            self.watchpoints.push(watchpoint);
+        } else {
+            self.watchpoints.retain(|other| *other != watchpoint);
+        }
+
+        "OK".to_string()
+    }
+
+    // 1ステップ実行し、止まる理由があれば返す
+    fn step(&mut self) -> Option<Stop> {
+        match self.computer.step() {
+            Ok(Some(RunResult::Exit(exit_code))) => Some(Stop::Exited(exit_code)),
+            // ホストに返されたECALLはプログラムの終了ではないので、SIGTRAPで止める
+            Ok(Some(_)) => Some(Stop::Signal(SIGTRAP)),
+            Ok(None) => {
+                let access = self.computer.processor().memory_access;
+                self.watchpoints.iter().find_map(|watchpoint| {
+                    watchpoint
+                        .hit(&access)
+                        .map(|(kind, address)| Stop::Watchpoint(kind, address))
+                })
+            }
+            Err(error) => Some(Stop::Fault(error.to_string())),
+        }
+    }
+
+    // ブレークポイントかウォッチポイントに当たるか、Ctrl-Cが届くまで実行する
+    fn resume(&mut self) -> Result<Stop, GdbError> {
+        let mut steps: u64 = 0;
+
+        loop {
+            if let Some(stop) = self.step() {
+                return Ok(stop);
+            }
+            if self.breakpoints.contains(&self.computer.processor().pc) {
+                return Ok(Stop::Breakpoint);
+            }
+
+            steps += 1;
+            if steps.is_multiple_of(INTERRUPT_POLL_INTERVAL) && self.interrupted()? {
+                return Ok(Stop::Signal(SIGINT));
+            }
+        }
+    }
+
+    fn stop_reply(&mut self, stop: Stop) -> Result<String, GdbError> {
+        let reply = match stop {
+            Stop::Signal(signal) => format!("S{:02x}", signal),
+            Stop::Breakpoint => format!("T{:02x}swbreak:;", SIGTRAP),
+            Stop::Watchpoint(kind, address) => format!("T{:02x}{}:{:x};", SIGTRAP, kind, address),
+            Stop::Fault(message) => {
+                self.send_packet(&format!(
+                    "O{}",
+                    encode_hex(format!("{}\n", message).as_bytes())
+                ))?;
+                format!("S{:02x}", SIGSEGV)
+            }
+            Stop::Exited(exit_code) => format!("W{:02x}", exit_code as u8),
+        };
+
+        Ok(reply)
+    }
+
+    // 実行中に届いたデータを読み、Ctrl-Cがあればtrueを返す
+    // 切断されていた場合も実行を止める
+    fn interrupted(&mut self) -> Result<bool, GdbError> {
+        self.stream
+            .set_nonblocking(true)
+            .map_err(GdbError::ConnectionError)?;
+        let mut buffer = [0; 256];
+        let result = self.stream.read(&mut buffer);
+        self.stream
+            .set_nonblocking(false)
+            .map_err(GdbError::ConnectionError)?;
+
+        match result {
+            Ok(0) => self.closed = true,
+            Ok(length) => self.received.extend(&buffer[..length]),
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => (),
+            Err(error) => return Err(GdbError::ConnectionError(error)),
+        }
+
+        if let Some(index) = self.received.iter().position(|&byte| byte == INTERRUPT) {
+            self.received.remove(index);
+            return Ok(true);
+        }
+
+        Ok(self.closed)
+    }
+
+    // $packet-data#checksum を読み、チェックサムが合っていれば'+'を返す
+    // 接続が切れたらNone
+    fn read_packet(&mut self) -> Result<Option<Vec<u8>>, GdbError> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => (),
+                Some(b'-') => {
+                    let packet = self.last_packet.clone();
+                    self.write_raw(&packet)?;
+                    continue;
+                }
+                // '+'や停止中のCtrl-Cは読み捨てる
+                Some(_) => continue,
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+
+            let (Some(high), Some(low)) = (self.read_byte()?, self.read_byte()?) else {
+                return Ok(None);
+            };
+            let received = std::str::from_utf8(&[high, low])
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+
+            if received == Some(checksum(&data)) {
+                self.write_raw(b"+")?;
+                return Ok(Some(data));
+            }
+            self.write_raw(b"-")?;
+        }
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>, GdbError> {
+        if self.received.is_empty() && !self.closed {
+            let mut buffer = [0; 1024];
+            let length = self
+                .stream
+                .read(&mut buffer)
+                .map_err(GdbError::ConnectionError)?;
+            self.received.extend(&buffer[..length]);
+            self.closed = length == 0;
+        }
+
+        Ok(self.received.pop_front())
+    }
+
+    fn send_packet(&mut self, data: &str) -> Result<(), GdbError> {
+        let packet = format!("${}#{:0>2x}", data, checksum(data.as_bytes()));
+        self.last_packet = packet.into_bytes();
+        let packet = self.last_packet.clone();
+        self.write_raw(&packet)
+    }
+
+    fn write_raw(&mut self, data: &[u8]) -> Result<(), GdbError> {
+        self.stream
+            .write_all(data)
+            .map_err(GdbError::ConnectionError)
+    }
+}
+
+// x0-x31とpc、名前の付いたCSRを並べたターゲット記述
+pub fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n\
+         <architecture>riscv:rv32</architecture>\n\
+         <feature name=\"org.gnu.gdb.riscv.cpu\">\n",
+    );
+    for regnum in 0..PC_REGNUM {
+        let reg_type = match regnum {
+            1 => "code_ptr",
+            2 => "data_ptr",
+            _ => "int",
+        };
+        xml.push_str(&format!(
+            "<reg name=\"{}\" bitsize=\"32\" type=\"{}\" regnum=\"{}\"/>\n",
+            abi_name(regnum),
+            reg_type,
+            regnum
+        ));
+    }
+    xml.push_str(&format!(
+        "<reg name=\"pc\" bitsize=\"32\" type=\"code_ptr\" regnum=\"{}\"/>\n</feature>\n",
+        PC_REGNUM
+    ));
+
+    xml.push_str("<feature name=\"org.gnu.gdb.riscv.csr\">\n");
+    for (csr, name) in CSR_NAMES {
+        xml.push_str(&format!(
+            "<reg name=\"{}\" bitsize=\"32\" type=\"int\" regnum=\"{}\"/>\n",
+            name,
+            CSR_REGNUM_BASE + csr
+        ));
+    }
+    xml.push_str("</feature>\n</target>\n");
+
+    xml
+}
+
+fn csr_index(regnum: u32) -> Option<u32> {
+    regnum
+        .checked_sub(CSR_REGNUM_BASE)
+        .filter(|&index| index < 4096)
+}
+
+// offset,lengthで指定された部分を返す。最後まで読めば'l'、続きがあれば'm'を付ける
+fn read_xfer(document: &str, range: &str) -> String {
+    let Some((offset, length)) = parse_address_length(range) else {
+        return "E01".to_string();
+    };
+
+    let start = (offset as usize).min(document.len());
+    let end = start.saturating_add(length as usize).min(document.len());
+    let marker = if end == document.len() { 'l' } else { 'm' };
+
+    format!("{}{}", marker, &document[start..end])
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+fn parse_hex(value: &str) -> Option<u32> {
+    u32::from_str_radix(value, 16).ok()
+}
+
+fn parse_address_length(args: &str) -> Option<(u32, u32)> {
+    let (address, length) = args.split_once(',')?;
+    Some((parse_hex(address)?, parse_hex(length)?))
+}
+
+// レジスタの値はターゲットのバイト順(リトルエンディアン)で16進数にする
+fn encode_register(value: u32) -> String {
+    encode_hex(&value.to_le_bytes())
+}
+
+fn decode_register(value: &str) -> Option<u32> {
+    let bytes: [u8; 4] = decode_hex(value)?.try_into().ok()?;
+    Some(u32::from_le_bytes(bytes))
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:0>2x}", byte)).collect()
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(value.get(index..index + 2)?, 16).ok())
+        .collect()
+}
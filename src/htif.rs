@@ -0,0 +1,43 @@
+// riscv-testsなどが使うHost-Target Interface(HTIF)
+// ゲストがtohostに書き込んだ値をホスト側で解釈する
+#[derive(Debug, Clone, Copy)]
+pub struct Htif {
+    pub tohost: u32,
+    pub fromhost: Option<u32>,
+    exit_code: Option<u32>,
+}
+
+impl Htif {
+    pub fn new(tohost: u32, fromhost: Option<u32>) -> Self {
+        Self {
+            tohost,
+            fromhost,
+            exit_code: None,
+        }
+    }
+
+    // tohostは64bitなので上位32bitへの書き込みも対象にする
+    pub fn is_tohost(&self, address: u32) -> bool {
+        address >= self.tohost && address < self.tohost + 8
+    }
+
+    // tohostに書き込まれた値を処理する
+    // ホストがコマンドを受け取りtohostをクリアすべき場合はtrueを返す
+    pub fn write_tohost(&mut self, value: u64) -> bool {
+        if value == 0 {
+            return false;
+        }
+
+        let device = value >> 56;
+        if device == 0 && value & 1 == 1 {
+            // 1ならpass、それ以外は(失敗したテスト番号 << 1) | 1
+            self.exit_code = Some((value >> 1) as u32);
+        }
+
+        true
+    }
+
+    pub fn exit_code(&self) -> Option<u32> {
+        self.exit_code
+    }
+}
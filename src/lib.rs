@@ -0,0 +1,18 @@
+pub mod bus;
+pub mod clint;
+pub mod computer;
+pub mod config;
+pub mod device;
+pub mod dram;
+pub mod elf;
+pub mod gdb;
+pub mod htif;
+pub mod memory;
+pub mod plic;
+pub mod processor;
+pub mod sparse_memory;
+pub mod symbol;
+pub mod test_suite;
+pub mod timer;
+pub mod trace;
+pub mod uart;
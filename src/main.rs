@@ -1,27 +1,366 @@
-mod bus;
-mod computer;
-mod dram;
-mod processor;
+use simple_riscv::bus::{Bus, DRAM_BASE};
+use simple_riscv::computer::{Computer, RunResult};
+use simple_riscv::config::{MachineConfig, MemoryBackend, MisalignedAccess, DEFAULT_DRAM_SIZE};
+use simple_riscv::elf::Elf;
+use simple_riscv::gdb::GdbStub;
+use simple_riscv::processor::riscv::rv32ui::disasm;
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+use simple_riscv::processor::riscv::rv32ui::RiscVUIProcessor;
+use simple_riscv::symbol::SymbolTable;
+use simple_riscv::test_suite::{self, TestOutcome, DEFAULT_STEP_LIMIT};
+use simple_riscv::timer::{Timer, DEFAULT_TIMEBASE};
+use simple_riscv::trace;
+use std::io::{self, BufWriter, Write};
+use std::net::TcpListener;
+use std::{env, fs::File, path::Path, process};
 
-use crate::processor::riscv::rv32ui::RiscVUIProcessor;
-use bus::Bus;
-use computer::Computer;
-use std::{env, path::Path};
+struct Options {
+    path: Option<String>,
+    test_suite: Option<String>,
+    step_limit: Option<u64>,
+    extensions: Extensions,
+    timebase: u32,
+    dram_base: u32,
+    dram_size: u32,
+    // メインメモリ以外のメモリ領域(base, size)
+    memory: Vec<(u32, u32)>,
+    memory_backend: MemoryBackend,
+    map_all: bool,
+    misaligned_access: MisalignedAccess,
+    log_commits: bool,
+    commit_log_file: Option<String>,
+    gdb_port: Option<u16>,
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        path: None,
+        test_suite: None,
+        step_limit: None,
+        // 実装している拡張は全て有効にしておき、--isaで絞る
+        extensions: Extensions::all(),
+        timebase: DEFAULT_TIMEBASE,
+        dram_base: DRAM_BASE,
+        dram_size: DEFAULT_DRAM_SIZE,
+        memory: Vec::new(),
+        memory_backend: MemoryBackend::Flat,
+        map_all: false,
+        misaligned_access: MisalignedAccess::Emulate,
+        log_commits: false,
+        commit_log_file: None,
+        gdb_port: None,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--test-suite" => options.test_suite = args.next(),
+            "--step-limit" => {
+                let limit = args.next().unwrap_or_default();
+                options.step_limit = Some(limit.parse().unwrap_or_else(|_| {
+                    eprintln!("Invalid value for --step-limit: {}", limit);
+                    process::exit(2);
+                }));
+            }
+            "--isa" => {
+                let isa = args.next().unwrap_or_default();
+                options.extensions = Extensions::parse(&isa).unwrap_or_else(|| {
+                    eprintln!("Unsupported ISA: {}", isa);
+                    process::exit(2);
+                });
+            }
+            // mtimeを1進めるのに必要なサイクル数。0は指定できない
+            "--timebase" => {
+                let timebase = args.next().unwrap_or_default();
+                options.timebase = match timebase.parse() {
+                    Ok(0) | Err(_) => {
+                        eprintln!("Invalid value for --timebase: {}", timebase);
+                        process::exit(2);
+                    }
+                    Ok(timebase) => timebase,
+                };
+            }
+            "--dram-base" => options.dram_base = parse_number_arg(&arg, args.next()),
+            "--dram-size" => options.dram_size = parse_size_arg(&arg, args.next()),
+            // --memory BASE:SIZE でメモリ領域を追加する
+            "--memory" => {
+                let region = args.next().unwrap_or_default();
+                let (base, size) = region.split_once(':').unwrap_or((&region, ""));
+                options.memory.push((
+                    parse_number_arg(&arg, Some(base.to_string())),
+                    parse_size_arg(&arg, Some(size.to_string())),
+                ));
+            }
+            // メモリを4KiBページ単位で確保する
+            "--sparse-memory" => options.memory_backend = MemoryBackend::Sparse,
+            // デバイス以外の32bitのアドレス空間を全てメモリにする
+            "--map-all" => options.map_all = true,
+            "--misaligned" => {
+                let policy = args.next().unwrap_or_default();
+                options.misaligned_access = match policy.as_str() {
+                    "emulate" => MisalignedAccess::Emulate,
+                    "trap" => MisalignedAccess::Trap,
+                    _ => {
+                        eprintln!("Invalid value for --misaligned: {}", policy);
+                        process::exit(2);
+                    }
+                };
+            }
+            // --trace fetch,mem=trace のようにカテゴリとレベルを指定する
+            "--trace" => {
+                let spec = args.next().unwrap_or_default();
+                if let Err(error) = trace::configure(&spec) {
+                    eprintln!("{}", error);
+                    process::exit(2);
+                }
+            }
+            // 指定しなければトレースは標準エラー出力に書く
+            "--trace-file" => {
+                let path = args.next().unwrap_or_default();
+                let file = File::create(&path).unwrap_or_else(|error| {
+                    eprintln!("Cannot create trace file {}: {}", path, error);
+                    process::exit(2);
+                });
+                trace::set_output(Box::new(BufWriter::new(file)));
+            }
+            // Spikeと同じ形式のコミットログ。指定しなければ標準エラー出力に書く
+            "--log-commits" => options.log_commits = true,
+            "--commit-log-file" => {
+                options.log_commits = true;
+                options.commit_log_file = args.next();
+            }
+            // GDBからの接続を待ち、実行を任せる。ポート番号は省略できない
+            "--gdb" => {
+                let port = args.next().unwrap_or_default();
+                options.gdb_port = Some(port.parse().unwrap_or_else(|_| {
+                    eprintln!("Invalid value for --gdb: {}", port);
+                    process::exit(2);
+                }));
+            }
+            _ => options.path = Some(arg),
+        }
+    }
+
+    options
+}
+
+// 0xで始まれば16進数として読む
+fn parse_number(value: &str) -> Option<u32> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(&hex.replace('_', ""), 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+// K/M/Gの接尾辞が付いたサイズを読む
+fn parse_size(value: &str) -> Option<u32> {
+    let (number, unit) = match value.char_indices().last()? {
+        (index, 'K' | 'k') => (&value[..index], 1 << 10),
+        (index, 'M' | 'm') => (&value[..index], 1 << 20),
+        (index, 'G' | 'g') => (&value[..index], 1 << 30),
+        _ => (value, 1),
+    };
+
+    parse_number(number)?.checked_mul(unit)
+}
+
+fn parse_number_arg(option: &str, value: Option<String>) -> u32 {
+    let value = value.unwrap_or_default();
+    parse_number(&value).unwrap_or_else(|| {
+        eprintln!("Invalid value for {}: {}", option, value);
+        process::exit(2);
+    })
+}
+
+fn parse_size_arg(option: &str, value: Option<String>) -> u32 {
+    let value = value.unwrap_or_default();
+    parse_size(&value).unwrap_or_else(|| {
+        eprintln!("Invalid size for {}: {}", option, value);
+        process::exit(2);
+    })
+}
+
+fn machine_config(options: &Options) -> MachineConfig {
+    // Flatでアドレス空間全体を確保すると数GBになる
+    if options.map_all && options.memory_backend != MemoryBackend::Sparse {
+        eprintln!("--map-all requires --sparse-memory");
+        process::exit(2);
+    }
+
+    let config = MachineConfig::new(options.dram_base, options.dram_size).and_then(|mut config| {
+        for &(base, size) in &options.memory {
+            config.add_memory(base, size)?;
+        }
+        config.set_memory_backend(options.memory_backend);
+        if options.map_all {
+            config.fill_address_space();
+        }
+        config.set_misaligned_access(options.misaligned_access);
+        Ok(config)
+    });
+
+    config.unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(2);
+    })
+}
+
+fn commit_log(options: &Options) -> Option<Box<dyn Write>> {
+    if !options.log_commits {
+        return None;
+    }
+
+    let output: Box<dyn Write> = match &options.commit_log_file {
+        Some(path) => Box::new(BufWriter::new(File::create(path).unwrap_or_else(|error| {
+            eprintln!("Cannot create commit log file {}: {}", path, error);
+            process::exit(2);
+        }))),
+        None => Box::new(BufWriter::new(io::stderr())),
+    };
+
+    Some(output)
+}
 
 fn main() {
-    println!("Hello, world!");
+    // simple-riscv disasm <file> でELFの.textなどを逆アセンブルする
+    if env::args().nth(1).as_deref() == Some("disasm") {
+        match env::args().nth(2) {
+            Some(path) => run_disasm(Path::new(&path)),
+            None => {
+                eprintln!("Usage: simple-riscv disasm <file>");
+                process::exit(2);
+            }
+        }
+        return;
+    }
 
-    let args: Vec<String> = env::args().collect();
-    let path = Path::new(&args[1]);
+    let options = parse_options();
+
+    if let Some(dir) = &options.test_suite {
+        run_test_suite(
+            Path::new(dir),
+            options.extensions,
+            options.step_limit.unwrap_or(DEFAULT_STEP_LIMIT),
+        );
+    } else if let Some(path) = &options.path {
+        run_program(Path::new(path), &options);
+    } else {
+        eprintln!("Usage: simple-riscv [--isa rv32imac] [--step-limit N] [--timebase N]");
+        eprintln!("                    [--dram-base ADDR] [--dram-size SIZE] [--memory ADDR:SIZE]");
+        eprintln!("                    [--sparse-memory [--map-all]] [--misaligned emulate|trap]");
+        eprintln!("                    [--trace CATEGORY[=LEVEL],...] [--trace-file PATH]");
+        eprintln!(
+            "                    [--log-commits] [--commit-log-file PATH] [--gdb PORT] <file>"
+        );
+        eprintln!("       simple-riscv --test-suite <dir> [--isa rv32imac] [--step-limit N]");
+        eprintln!("       simple-riscv disasm <file>");
+        process::exit(2);
+    }
+}
 
-    let bus = Bus::new();
-    let processor = RiscVUIProcessor::new();
+fn run_program(path: &Path, options: &Options) {
+    let config = machine_config(options);
+    let mut bus = Bus::new(&config);
+    bus.clint.timer = Timer::new(options.timebase);
+    bus.uart.connect_stdin();
+    let mut processor = RiscVUIProcessor::with_extensions(options.extensions);
+    processor.commit_log = commit_log(options);
 
     let mut emulator = Computer::new(processor, bus);
 
-    emulator.load_from_file(0x80000000, path).unwrap();
+    emulator
+        .load_from_file(config.reset_vector(), path)
+        .unwrap();
+
+    if let Some(port) = options.gdb_port {
+        run_gdb_server(&mut emulator, port);
+        trace::flush();
+        return;
+    }
+
+    let result = emulator.run_with_step_limit(options.step_limit);
+    trace::flush();
+    if let Some(output) = emulator.processor_mut().commit_log.as_mut() {
+        let _ = output.flush();
+    }
+
+    // 標準出力はUARTの出力に使うので、結果は標準エラー出力に書く
+    match result {
+        Ok(RunResult::Ecall) => (),
+        Ok(RunResult::StepLimit) => eprintln!("Step limit reached"),
+        Ok(RunResult::Exit(0)) => eprintln!("PASS"),
+        Ok(RunResult::Exit(test_number)) => {
+            eprintln!("FAIL: test {}", test_number);
+            process::exit(1);
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(2);
+        }
+    }
+}
+
+// 1つの接続だけを受け付け、切断されたら終了する
+fn run_gdb_server(emulator: &mut Computer<RiscVUIProcessor>, port: u16) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap_or_else(|error| {
+        eprintln!("Cannot listen on port {}: {}", port, error);
+        process::exit(2);
+    });
+    eprintln!("Waiting for GDB connection on 127.0.0.1:{}", port);
+
+    let result = listener
+        .accept()
+        .map_err(|error| error.to_string())
+        .and_then(|(stream, _)| {
+            GdbStub::new(emulator, stream)
+                .run()
+                .map_err(|error| error.to_string())
+        });
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+// シンボルの先頭にはobjdumpと同じようにラベルを表示する
+fn run_disasm(path: &Path) {
+    let data = std::fs::read(path).unwrap_or_else(|error| {
+        eprintln!("Cannot read {}: {}", path.display(), error);
+        process::exit(2);
+    });
+
+    let result = Elf::parse(&data).and_then(|elf| {
+        let symbols = SymbolTable::from_elf_symbols(&elf.symbols()?);
+        Ok((disasm::disassemble_text(&elf)?, symbols))
+    });
+    let (sections, symbols) = result.unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(1);
+    });
+
+    for (name, lines) in sections {
+        println!("Disassembly of section {}:", name);
+        for line in lines {
+            if let Some((symbol, 0)) = symbols.lookup(line.address) {
+                println!();
+                println!("{:0>8x} <{}>:", line.address, symbol.name);
+            }
+            println!("{}", line);
+        }
+        println!();
+    }
+}
+
+// cargo run -- --test-suite test [--isa rv32imac] [--step-limit N]
+fn run_test_suite(dir: &Path, extensions: Extensions, step_limit: u64) {
+    let reports = test_suite::run_test_suite(dir, extensions, step_limit).unwrap_or_else(|error| {
+        eprintln!("Cannot run test suite in {}: {}", dir.display(), error);
+        process::exit(2);
+    });
+
+    test_suite::print_summary(&reports);
 
-    if let Result::Err(error) = emulator.run() {
-        println!("{}", error);
+    if reports.iter().any(|r| r.outcome != TestOutcome::Pass) {
+        process::exit(1);
     }
 }
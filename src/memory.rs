@@ -0,0 +1,65 @@
+use crate::{
+    config::MemoryBackend, device::Device, dram::Dram, processor::ProcessorError,
+    sparse_memory::SparseMemory,
+};
+
+// Busに載せるメモリ。MachineConfigのMemoryBackendでどちらを使うか選ぶ
+#[derive(Debug, Clone)]
+pub enum Memory {
+    Flat(Dram),
+    Sparse(SparseMemory),
+}
+
+impl Memory {
+    pub fn new(backend: MemoryBackend, size: u32) -> Self {
+        match backend {
+            MemoryBackend::Flat => Memory::Flat(Dram::new(size)),
+            MemoryBackend::Sparse => Memory::Sparse(SparseMemory::new(size)),
+        }
+    }
+
+    // デバッガ用の&selfでの読み出し。メモリの読み出しには副作用が無い
+    pub fn read8(&self, offset: u32) -> Result<u8, ProcessorError> {
+        match self {
+            Memory::Flat(dram) => dram.read8(offset),
+            Memory::Sparse(memory) => memory.read8(offset),
+        }
+    }
+
+    fn device_mut(&mut self) -> &mut dyn Device {
+        match self {
+            Memory::Flat(dram) => dram,
+            Memory::Sparse(memory) => memory,
+        }
+    }
+}
+
+impl Device for Memory {
+    fn read8(&mut self, offset: u32) -> Result<u8, ProcessorError> {
+        self.device_mut().read8(offset)
+    }
+
+    fn read16(&mut self, offset: u32) -> Result<u16, ProcessorError> {
+        self.device_mut().read16(offset)
+    }
+
+    fn read32(&mut self, offset: u32) -> Result<u32, ProcessorError> {
+        self.device_mut().read32(offset)
+    }
+
+    fn write8(&mut self, offset: u32, value: u8) -> Result<(), ProcessorError> {
+        self.device_mut().write8(offset, value)
+    }
+
+    fn write16(&mut self, offset: u32, value: u16) -> Result<(), ProcessorError> {
+        self.device_mut().write16(offset, value)
+    }
+
+    fn write32(&mut self, offset: u32, value: u32) -> Result<(), ProcessorError> {
+        self.device_mut().write32(offset, value)
+    }
+
+    fn write_bytes(&mut self, offset: u32, data: &[u8]) -> Result<(), ProcessorError> {
+        self.device_mut().write_bytes(offset, data)
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{
+    device::{Device, DeviceError, DeviceErrorType},
+    processor::ProcessorError,
+};
+
+// Platform-Level Interrupt Controller(PLIC)
+// デバイスからの割り込み線をまとめ、コンテキストごとにmip.MEIP/SEIPへ通知する
+pub const PLIC_BASE: u32 = 0x0c00_0000;
+pub const PLIC_SIZE: u32 = 0x0400_0000;
+
+// 割り込みソース数。ソース0は「割り込み無し」を表すので使わない
+pub const PLIC_SOURCES: u32 = 32;
+
+// コンテキスト0はhart0のMモード、1はhart0のSモード
+pub const CONTEXT_MACHINE: usize = 0;
+pub const CONTEXT_SUPERVISOR: usize = 1;
+const CONTEXTS: usize = 2;
+
+const PRIORITY_BASE: u32 = 0x00_0000;
+const PENDING_BASE: u32 = 0x00_1000;
+const ENABLE_BASE: u32 = 0x00_2000;
+const ENABLE_STRIDE: u32 = 0x80;
+const CONTEXT_BASE: u32 = 0x20_0000;
+const CONTEXT_STRIDE: u32 = 0x1000;
+const THRESHOLD: u32 = 0x0;
+const CLAIM_COMPLETE: u32 = 0x4;
+
+const PRIORITY_MASK: u32 = 0x7;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Plic {
+    priority: [u32; PLIC_SOURCES as usize],
+    // デバイスから入ってくる割り込み線のレベル
+    lines: u32,
+    pending: u32,
+    // claimされてcompleteされていないソース
+    in_service: u32,
+    enable: [u32; CONTEXTS],
+    threshold: [u32; CONTEXTS],
+}
+
+impl Default for Plic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plic {
+    pub fn new() -> Self {
+        Self {
+            priority: [0; PLIC_SOURCES as usize],
+            lines: 0,
+            pending: 0,
+            in_service: 0,
+            enable: [0; CONTEXTS],
+            threshold: [0; CONTEXTS],
+        }
+    }
+
+    // デバイスが割り込み線のレベルを変える。レベルトリガとして扱う
+    pub fn set_interrupt_line(&mut self, source: u32, level: bool) {
+        if source == 0 || source >= PLIC_SOURCES {
+            return;
+        }
+
+        let bit = 1 << source;
+        if level {
+            self.lines |= bit;
+            if self.in_service & bit == 0 {
+                self.pending |= bit;
+            }
+        } else {
+            self.lines &= !bit;
+            self.pending &= !bit;
+        }
+    }
+
+    // コンテキストに通知すべき割り込みがあるか
+    pub fn is_interrupting(&self, context: usize) -> bool {
+        self.highest_pending(context).is_some()
+    }
+
+    // 有効かつ閾値より優先度の高い割り込みのうち、優先度が最も高いもの(同じならIDの小さいもの)
+    fn highest_pending(&self, context: usize) -> Option<u32> {
+        let candidates = self.pending & self.enable[context];
+
+        (1..PLIC_SOURCES)
+            .filter(|source| candidates & 1 << source != 0)
+            .filter(|&source| self.priority[source as usize] > self.threshold[context])
+            .min_by_key(|&source| (u32::MAX - self.priority[source as usize], source))
+    }
+
+    fn claim(&mut self, context: usize) -> u32 {
+        let Some(source) = self.highest_pending(context) else {
+            return 0;
+        };
+
+        self.pending &= !(1 << source);
+        self.in_service |= 1 << source;
+        crate::trace!(
+            Device,
+            Debug,
+            "PLIC: context {} claimed source {}",
+            context,
+            source
+        );
+        source
+    }
+
+    fn complete(&mut self, source: u32) {
+        if source == 0 || source >= PLIC_SOURCES {
+            return;
+        }
+
+        let bit = 1 << source;
+        self.in_service &= !bit;
+        crate::trace!(Device, Debug, "PLIC: completed source {}", source);
+        // 割り込み線が上がったままなら再びpendingにする
+        if self.lines & bit != 0 {
+            self.pending |= bit;
+        }
+    }
+
+    // offsetはPLIC_BASEからの4byte境界のオフセット。claimは読むと副作用がある
+    pub fn read32(&mut self, offset: u32) -> u32 {
+        match offset {
+            PRIORITY_BASE..PENDING_BASE => self
+                .priority
+                .get(((offset - PRIORITY_BASE) / 4) as usize)
+                .copied()
+                .unwrap_or(0),
+            PENDING_BASE => self.pending,
+            ENABLE_BASE..CONTEXT_BASE => match Self::enable_context(offset) {
+                Some(context) => self.enable[context],
+                None => 0,
+            },
+            CONTEXT_BASE.. => match Self::context_register(offset) {
+                Some((context, THRESHOLD)) => self.threshold[context],
+                Some((context, CLAIM_COMPLETE)) => self.claim(context),
+                _ => 0,
+            },
+            _ => 0,
+        }
+    }
+
+    pub fn write32(&mut self, offset: u32, value: u32) {
+        match offset {
+            PRIORITY_BASE..PENDING_BASE => {
+                let source = ((offset - PRIORITY_BASE) / 4) as usize;
+                if (1..PLIC_SOURCES as usize).contains(&source) {
+                    self.priority[source] = value & PRIORITY_MASK;
+                }
+            }
+            ENABLE_BASE..CONTEXT_BASE => {
+                if let Some(context) = Self::enable_context(offset) {
+                    // ソース0は常に無効
+                    self.enable[context] = value & !1;
+                }
+            }
+            CONTEXT_BASE.. => match Self::context_register(offset) {
+                Some((context, THRESHOLD)) => self.threshold[context] = value & PRIORITY_MASK,
+                Some((_, CLAIM_COMPLETE)) => self.complete(value),
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+
+    // ソースが32個なのでenableは各コンテキストの先頭ワードのみ
+    fn enable_context(offset: u32) -> Option<usize> {
+        let offset = offset - ENABLE_BASE;
+        let context = (offset / ENABLE_STRIDE) as usize;
+
+        (offset.is_multiple_of(ENABLE_STRIDE) && context < CONTEXTS).then_some(context)
+    }
+
+    fn context_register(offset: u32) -> Option<(usize, u32)> {
+        let offset = offset - CONTEXT_BASE;
+        let context = (offset / CONTEXT_STRIDE) as usize;
+
+        (context < CONTEXTS).then_some((context, offset % CONTEXT_STRIDE))
+    }
+}
+
+// レジスタは32bitアクセスのみ
+impl Device for Plic {
+    fn read8(&mut self, offset: u32) -> Result<u8, ProcessorError> {
+        Err(unsupported_access(offset, 1))
+    }
+
+    fn read16(&mut self, offset: u32) -> Result<u16, ProcessorError> {
+        Err(unsupported_access(offset, 2))
+    }
+
+    fn read32(&mut self, offset: u32) -> Result<u32, ProcessorError> {
+        if offset.is_multiple_of(4) {
+            Ok(Plic::read32(self, offset))
+        } else {
+            Err(unsupported_access(offset, 4))
+        }
+    }
+
+    fn write8(&mut self, offset: u32, _value: u8) -> Result<(), ProcessorError> {
+        Err(unsupported_access(offset, 1))
+    }
+
+    fn write16(&mut self, offset: u32, _value: u16) -> Result<(), ProcessorError> {
+        Err(unsupported_access(offset, 2))
+    }
+
+    fn write32(&mut self, offset: u32, value: u32) -> Result<(), ProcessorError> {
+        if offset.is_multiple_of(4) {
+            Plic::write32(self, offset, value);
+            Ok(())
+        } else {
+            Err(unsupported_access(offset, 4))
+        }
+    }
+}
+
+fn unsupported_access(offset: u32, width: u32) -> ProcessorError {
+    DeviceError::new(DeviceErrorType::UnsupportedAccess { offset, width })
+}
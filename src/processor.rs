@@ -1,13 +1,21 @@
 use std::fmt::Display;
+use std::rc::Rc;
 
 use crate::bus::Bus;
+use crate::config::MachineConfig;
+use crate::symbol::SymbolTable;
 
 pub mod riscv;
 
 pub trait Processor {
     fn increment(&mut self, computer: &mut Bus) -> Result<ProcessorResult, ProcessorError>;
+    // メモリ構成に合わせてpcとスタックポインターを初期化する
+    fn reset(&mut self, config: &MachineConfig);
+    fn set_pc(&mut self, pc: u32);
+    fn set_symbols(&mut self, symbols: Rc<SymbolTable>);
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProcessorResult {
     OK,
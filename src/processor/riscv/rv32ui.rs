@@ -1,77 +1,365 @@
+pub mod commit_log;
+pub mod compressed;
 pub mod cs_register;
 pub mod decode;
+pub mod disasm;
 pub mod execute;
+pub mod extension;
 pub mod fetch;
+pub mod mmu;
+pub mod trap;
 pub mod writeback;
 pub mod x_register;
 
+use commit_log::Commit;
+use cs_register::{
+    ControlAndStatusRegister, MIDELEG, MIE, MIP, MIP_MEIP, MIP_MSIP, MIP_MTIP, MIP_SEIP, MSTATUS,
+    MSTATUS_MIE, MSTATUS_SIE, MSTATUS_TSR, MSTATUS_TVM, MSTATUS_TW, SATP,
+};
 use decode::Decode;
+use decode::DecodeResult;
 use decode::Opcode;
 use execute::Execute;
-use x_register::XRegisters;
-use cs_register::ControlAndStatusRegister;
+use extension::{Extension, Extensions};
 use fetch::Fetch;
-use writeback::Writeback;
+use mmu::Mmu;
+use trap::{Exception, Interrupt, Privilege, Trap, TrapError, TrapErrorType};
+use writeback::{Writeback, WritebackResult};
+use x_register::XRegisters;
 
 use crate::processor::Processor;
 use crate::processor::ProcessorError;
 use crate::processor::ProcessorResult;
-use crate::Bus;
+use crate::symbol::SymbolTable;
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::bus::Bus;
+use crate::bus::DRAM_BASE;
+use crate::config::{MachineConfig, MisalignedAccess};
+use crate::plic::{CONTEXT_MACHINE, CONTEXT_SUPERVISOR};
 
 pub struct RiscVUIProcessor {
     pub xregs: XRegisters,
     pub csr: ControlAndStatusRegister,
     pub pc: u32,
+    pub privilege: Privilege,
+    pub extensions: Extensions,
 
     pub fetch: Fetch,
     pub decode: Decode,
     pub execute: Execute,
     pub writeback: Writeback,
+    pub trap: Trap,
+    pub mmu: Mmu,
+
+    pub symbols: Rc<SymbolTable>,
+
+    // 直前に実行した命令のメモリアクセス。デバッガのウォッチポイントに使う
+    pub memory_access: WritebackResult,
+    // Someならリタイアした命令をSpikeの --log-commits 形式で書き出す
+    pub commit_log: Option<Box<dyn Write>>,
+}
+
+impl Default for RiscVUIProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RiscVUIProcessor {
+    // CLIと同じく実装している全ての拡張を有効にする
     pub fn new() -> Self {
+        Self::with_extensions(Extensions::all())
+    }
+
+    pub fn with_extensions(extensions: Extensions) -> Self {
         Self {
             xregs: XRegisters::new(),
-            csr: ControlAndStatusRegister::new(),
-            pc: 0x80000000 + 0x1000,
+            csr: ControlAndStatusRegister::with_extensions(extensions),
+            pc: DRAM_BASE,
+            privilege: Privilege::Machine,
+            extensions,
             fetch: Fetch(),
             decode: Decode(),
             execute: Execute(),
-            writeback: Writeback(),
+            writeback: Writeback {
+                misaligned_access: MisalignedAccess::default(),
+            },
+            trap: Trap(),
+            mmu: Mmu::new(),
+            symbols: Rc::new(SymbolTable::new()),
+            memory_access: WritebackResult::default(),
+            commit_log: None,
         }
     }
-}
 
-impl Processor for RiscVUIProcessor {
-    // todo
-    fn increment(&mut self, bus: &mut Bus) -> Result<ProcessorResult, ProcessorError> {
-        println!("pc: 0x{:0>8x}", self.pc - 0x1000); // !DO
+    // 同期例外を発生させ、トラップハンドラへ移る
+    // トラップハンドラが設定されていなければ何も変更せずにfalseを返す
+    pub fn raise_exception(&mut self, exception: Exception) -> bool {
+        crate::trace!(
+            Trap,
+            Info,
+            "Processor: EXCEPTION: {:?} at {}",
+            exception,
+            self.symbols.format_address(self.pc)
+        );
+
+        let Some((pc, privilege)) = self.trap.enter(
+            exception.cause(),
+            exception.tval(),
+            self.pc,
+            self.privilege,
+            &mut self.csr,
+        ) else {
+            return false;
+        };
+        self.pc = pc;
+        self.privilege = privilege;
+
+        true
+    }
+
+    // 割り込みを発生させ、トラップハンドラへ移る
+    pub fn raise_interrupt(&mut self, interrupt: Interrupt) {
+        crate::trace!(
+            Trap,
+            Info,
+            "Processor: INTERRUPT: {:?} at {}",
+            interrupt,
+            self.symbols.format_address(self.pc)
+        );
+
+        // pending_interruptはトラップハンドラがある割り込みだけを返す
+        if let Some((pc, privilege)) =
+            self.trap
+                .enter(interrupt.cause(), 0, self.pc, self.privilege, &mut self.csr)
+        {
+            self.pc = pc;
+            self.privilege = privilege;
+        }
+    }
+
+    // 受け付けられる割り込みのうち最も優先度の高いものを返す
+    fn pending_interrupt(&self) -> Option<Interrupt> {
+        let mstatus = self.csr.read(MSTATUS);
+        let pending = self.csr.read(MIP) & self.csr.read(MIE);
+        let mideleg = self.csr.read(MIDELEG);
+
+        // 現在より高い特権レベルへの割り込みは常に、同じ特権レベルならxIEが立っていれば受け付ける
+        // トラップハンドラが設定されていない特権レベルへの割り込みは受け付けない
+        let machine_enabled = self.csr.mtvec().is_some()
+            && (self.privilege < Privilege::Machine || mstatus & MSTATUS_MIE != 0);
+        let supervisor_enabled = self.csr.stvec().is_some()
+            && (self.privilege < Privilege::Supervisor
+                || (self.privilege == Privilege::Supervisor && mstatus & MSTATUS_SIE != 0));
+
+        let mut enabled = 0;
+        if machine_enabled {
+            enabled |= pending & !mideleg;
+        }
+        if supervisor_enabled {
+            enabled |= pending & mideleg;
+        }
+
+        Interrupt::PRIORITY
+            .into_iter()
+            .find(|interrupt| enabled & interrupt.bit() != 0)
+    }
+
+    // 1命令を実行する。ゲストが起こした例外はErrで返す
+    fn step(&mut self, bus: &mut Bus) -> Result<(), Exception> {
+        self.mmu.update(self.privilege, &self.csr);
+        // コミットログには実行前のpcと特権レベルを書く
+        let (commit_pc, commit_privilege) = (self.pc, self.privilege);
 
-        println!("Xregisters: {}", self.xregs);
-        let inst = self.fetch.fetch(self.pc, &bus)?;
-        let decode_res = self.decode.decode(inst, &self.xregs)?;
+        let inst = self
+            .fetch
+            .fetch(self.pc, bus, &self.extensions, &mut self.mmu)?;
+        let decode_res = self.decode.decode(inst, &self.xregs, &self.extensions)?;
+        crate::trace!(
+            Decode,
+            Debug,
+            "Decode: {}",
+            disasm::format_instruction(&decode_res, self.pc)
+        );
+        if !self.is_permitted(&decode_res) {
+            return Err(Exception::IllegalInstruction(inst));
+        }
         let execute_res = self.execute.execute(decode_res, self.pc)?;
-        self.writeback
-            .writeback(decode_res, execute_res, &mut self.xregs, &mut self.csr, bus)?;
+
+        // 飛び先が命令境界に揃っていなければ、rdを書き換える前に分岐命令自身で例外にする
+        let alignment = if self.extensions.contains(Extension::C) {
+            2
+        } else {
+            4
+        };
+        if let Some(target) = execute_res.br_target.or(execute_res.jmp_target) {
+            if !target.is_multiple_of(alignment) {
+                return Err(Exception::InstructionAddressMisaligned(target));
+            }
+        }
+
+        let writeback_res = self.writeback.writeback(
+            decode_res,
+            execute_res,
+            &mut self.xregs,
+            &mut self.csr,
+            bus,
+            &mut self.mmu,
+        )?;
+        self.memory_access = writeback_res;
 
         // この処理はFetchでやるべき
         if let Some(br_target) = execute_res.br_target {
             self.pc = br_target;
-            println!("Processor: BR TARGET: {:x}", br_target);
+            crate::trace!(
+                Exec,
+                Debug,
+                "Processor: BR TARGET: {}",
+                self.symbols.format_address(br_target)
+            );
         } else if let Some(jmp_target) = execute_res.jmp_target {
             self.pc = jmp_target;
-            println!("Processor: JMP TARGET: {:x}", jmp_target);
+            crate::trace!(
+                Exec,
+                Debug,
+                "Processor: JMP TARGET: {}",
+                self.symbols.format_address(jmp_target)
+            );
+        } else if decode_res.opcode == Opcode::MRET {
+            let (pc, privilege) = self.trap.mret(&mut self.csr);
+            self.pc = pc;
+            self.privilege = privilege;
+            crate::trace!(
+                Trap,
+                Info,
+                "Processor: MRET: {}",
+                self.symbols.format_address(pc)
+            );
+        } else if decode_res.opcode == Opcode::SRET {
+            let (pc, privilege) = self.trap.sret(&mut self.csr);
+            self.pc = pc;
+            self.privilege = privilege;
+            crate::trace!(
+                Trap,
+                Info,
+                "Processor: SRET: {}",
+                self.symbols.format_address(pc)
+            );
         } else if decode_res.opcode == Opcode::ECALL {
-            self.pc = self.csr.read(0x305);
-            println!("Processor: ECALL!!!!");
-            return Ok(ProcessorResult::ECALL);
+            return Err(Exception::EnvironmentCall(self.privilege));
+        } else if decode_res.opcode == Opcode::EBREAK {
+            return Err(Exception::Breakpoint(self.pc));
         } else {
-            self.pc += 4;
+            self.pc += decode_res.inst_len;
+        }
+
+        // 例外になった命令はリタイアしていないので書かない
+        if let Some(output) = self.commit_log.as_mut() {
+            let commit = Commit::new(
+                commit_privilege,
+                commit_pc,
+                inst,
+                &decode_res,
+                &self.xregs,
+                writeback_res,
+            );
+            let _ = writeln!(output, "{}", commit);
+        }
+
+        Ok(())
+    }
+
+    // 現在の特権レベルで実行できる命令かどうか
+    fn is_permitted(&self, decode_res: &DecodeResult) -> bool {
+        let mstatus = self.csr.read(MSTATUS);
+        let privilege = self.privilege;
+        // mstatus.TVM/TSR/TWが立っているとSモードでは実行できない
+        let supervisor_allowed = |trap_bit: u32| {
+            privilege == Privilege::Machine
+                || (privilege == Privilege::Supervisor && mstatus & trap_bit == 0)
+        };
+
+        match decode_res.opcode {
+            _ if decode_res.is_csr() => {
+                self.csr
+                    .is_accessible(decode_res.csr, privilege, decode_res.writes_csr())
+                    && (decode_res.csr != SATP || supervisor_allowed(MSTATUS_TVM))
+            }
+            Opcode::MRET => privilege == Privilege::Machine,
+            Opcode::SRET => supervisor_allowed(MSTATUS_TSR),
+            Opcode::SFENCEVMA => supervisor_allowed(MSTATUS_TVM),
+            // Uモードで実行できるかは実装依存だが、ここでは不正命令にする
+            Opcode::WFI => supervisor_allowed(MSTATUS_TW),
+            _ => true,
+        }
+    }
+}
+
+impl Processor for RiscVUIProcessor {
+    fn increment(&mut self, bus: &mut Bus) -> Result<ProcessorResult, ProcessorError> {
+        crate::trace!(Exec, Debug, "pc: {}", self.symbols.format_address(self.pc));
+        crate::trace!(Exec, Trace, "Xregisters: {}", self.xregs);
+        self.memory_access = WritebackResult::default();
+
+        self.csr
+            .set_interrupt_pending(MIP_MSIP, bus.clint.software_interrupt());
+        self.csr
+            .set_interrupt_pending(MIP_MTIP, bus.clint.timer_interrupt());
+        self.csr
+            .set_interrupt_pending(MIP_MEIP, bus.plic.is_interrupting(CONTEXT_MACHINE));
+        self.csr
+            .set_interrupt_pending(MIP_SEIP, bus.plic.is_interrupting(CONTEXT_SUPERVISOR));
+
+        let pc = self.pc;
+        self.csr.set_time(bus.clint.timer.mtime());
+
+        // 割り込みは命令と命令の間で受け付ける。このサイクルは命令を実行せずにトラップに入る
+        let interrupt = self.pending_interrupt();
+        let step_result = match interrupt {
+            Some(_) => Ok(()),
+            None => self.step(bus),
+        };
+        self.csr.count(interrupt.is_none() && step_result.is_ok());
+        bus.tick();
+
+        if let Some(interrupt) = interrupt {
+            self.raise_interrupt(interrupt);
+            return Ok(ProcessorResult::OK);
         }
 
-        println!();
+        let result = match step_result {
+            Ok(()) => ProcessorResult::OK,
+            Err(exception) => {
+                let handled = self.raise_exception(exception);
+                match exception {
+                    _ if handled => ProcessorResult::OK,
+                    // トラップハンドラが設定されていなければホストに処理を返す
+                    Exception::EnvironmentCall(_) => ProcessorResult::ECALL,
+                    _ => {
+                        return Err(TrapError::new(TrapErrorType::UnhandledException(
+                            exception, pc,
+                        )))
+                    }
+                }
+            }
+        };
+
+        Ok(result)
+    }
+
+    fn reset(&mut self, config: &MachineConfig) {
+        self.pc = config.reset_vector();
+        self.xregs.write(2, config.initial_stack_pointer());
+        self.writeback.misaligned_access = config.misaligned_access();
+    }
+
+    fn set_pc(&mut self, pc: u32) {
+        self.pc = pc;
+    }
 
-        Ok(ProcessorResult::OK)
+    fn set_symbols(&mut self, symbols: Rc<SymbolTable>) {
+        self.symbols = symbols;
     }
 }
@@ -0,0 +1,73 @@
+use std::fmt::Display;
+
+use super::decode::DecodeResult;
+use super::trap::Privilege;
+use super::writeback::WritebackResult;
+use super::x_register::XRegisters;
+
+// リタイアした1命令分の記録。Spikeの --log-commits と同じ形式で表示する
+// core   0: 3 0x80000004 (0x00052283) x5  0x00000005 mem 0x80001000
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Commit {
+    // 命令を実行したときの特権レベル
+    pub privilege: Privilege,
+    pub pc: u32,
+    // RVC命令は展開する前の16bit
+    pub inst: u32,
+    pub inst_len: u32,
+    // 書き込んだレジスタの番号と値。x0は含めない
+    pub rd: Option<(u32, u32)>,
+    pub memory: WritebackResult,
+}
+
+impl Commit {
+    pub fn new(
+        privilege: Privilege,
+        pc: u32,
+        inst: u32,
+        decode: &DecodeResult,
+        xregs: &XRegisters,
+        memory: WritebackResult,
+    ) -> Self {
+        Self {
+            privilege,
+            pc,
+            inst,
+            inst_len: decode.inst_len,
+            rd: decode
+                .writes_rd()
+                .then(|| (decode.rd, xregs.read(decode.rd))),
+            memory,
+        }
+    }
+}
+
+// レジスタ、ロード、ストアの順に並べる。ストアの値はアクセス幅の桁数で表示する
+impl Display for Commit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "core   0: {} 0x{:0>8x} ", self.privilege as u32, self.pc)?;
+        if self.inst_len == 2 {
+            write!(f, "(0x{:0>4x})", self.inst)?;
+        } else {
+            write!(f, "(0x{:0>8x})", self.inst)?;
+        }
+
+        if let Some((rd, value)) = self.rd {
+            write!(f, " x{:<2} 0x{:0>8x}", rd, value)?;
+        }
+        if let Some((address, _)) = self.memory.load {
+            write!(f, " mem 0x{:0>8x}", address)?;
+        }
+        if let Some((address, value, width)) = self.memory.store {
+            write!(
+                f,
+                " mem 0x{:0>8x} 0x{:0>digits$x}",
+                address,
+                value,
+                digits = width as usize * 2
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+use bitmatch::bitmatch;
+
+// RVC命令(16bit)を同じ動作をする32bit命令に展開する
+// 展開後は通常の命令と同じようにDecodeで解釈する
+
+const OP_IMM: u32 = 0b0010011;
+const OP: u32 = 0b0110011;
+const LOAD: u32 = 0b0000011;
+const STORE: u32 = 0b0100011;
+const BRANCH: u32 = 0b1100011;
+const JAL: u32 = 0b1101111;
+const JALR: u32 = 0b1100111;
+const LUI: u32 = 0b0110111;
+
+const EBREAK: u32 = 0x0010_0073;
+
+pub fn is_compressed(inst: u32) -> bool {
+    inst & 0b11 != 0b11
+}
+
+#[bitmatch]
+pub fn expand(inst: u16) -> Option<u32> {
+    #[bitmatch]
+    match inst {
+        // C.ADDI4SPN
+        "000_aabbbbcd_rrr_00" => {
+            let nzuimm: u16 = bitpack!("bbbbaadc00");
+            (nzuimm != 0).then(|| i_type(nzuimm as i32, 2, 0b000, creg(r), OP_IMM))
+        }
+        // C.LW
+        "010_aaasssbc_rrr_00" => {
+            let uimm: u8 = bitpack!("caaab00");
+            Some(i_type(uimm as i32, creg(s), 0b010, creg(r), LOAD))
+        }
+        // C.SW
+        "110_aaasssbc_ttt_00" => {
+            let uimm: u8 = bitpack!("caaab00");
+            Some(s_type(uimm as i32, creg(t), creg(s), 0b010))
+        }
+
+        // C.ADDI (rd = 0 は C.NOP)
+        "000_arrrrrbbbbb_01" => {
+            let imm: u8 = bitpack!("abbbbb");
+            Some(i_type(sext(imm, 6), r as u32, 0b000, r as u32, OP_IMM))
+        }
+        // C.JAL
+        "001_aaaaaaaaaaa_01" => Some(j_type(cj_offset(a), 1)),
+        // C.LI
+        "010_arrrrrbbbbb_01" => {
+            let imm: u8 = bitpack!("abbbbb");
+            Some(i_type(sext(imm, 6), 0, 0b000, r as u32, OP_IMM))
+        }
+        // C.ADDI16SP
+        "011_a00010bcdde_01" => {
+            let nzimm: u16 = bitpack!("addceb0000");
+            (nzimm != 0).then(|| i_type(sext(nzimm, 10), 2, 0b000, 2, OP_IMM))
+        }
+        // C.LUI
+        "011_arrrrrbbbbb_01" => {
+            let nzimm: u8 = bitpack!("abbbbb");
+            (nzimm != 0).then(|| (sext(nzimm, 6) as u32) << 12 | (r as u32) << 7 | LUI)
+        }
+        // C.SRLI (RV32ではshamt[5]は0)
+        "100_000rrrsssss_01" => Some(shift_type(0b0000000, s as u32, creg(r), 0b101)),
+        // C.SRAI
+        "100_001rrrsssss_01" => Some(shift_type(0b0100000, s as u32, creg(r), 0b101)),
+        // C.ANDI
+        "100_a10rrrbbbbb_01" => {
+            let imm: u8 = bitpack!("abbbbb");
+            Some(i_type(sext(imm, 6), creg(r), 0b111, creg(r), OP_IMM))
+        }
+        // C.SUB, C.XOR, C.OR, C.AND
+        "100_011rrr00sss_01" => Some(r_type(0b0100000, creg(s), creg(r), 0b000, creg(r))),
+        "100_011rrr01sss_01" => Some(r_type(0b0000000, creg(s), creg(r), 0b100, creg(r))),
+        "100_011rrr10sss_01" => Some(r_type(0b0000000, creg(s), creg(r), 0b110, creg(r))),
+        "100_011rrr11sss_01" => Some(r_type(0b0000000, creg(s), creg(r), 0b111, creg(r))),
+        // C.J
+        "101_aaaaaaaaaaa_01" => Some(j_type(cj_offset(a), 0)),
+        // C.BEQZ, C.BNEZ
+        "110_aaasssbbbbb_01" => Some(b_type(cb_offset(a, b), 0, creg(s), 0b000)),
+        "111_aaasssbbbbb_01" => Some(b_type(cb_offset(a, b), 0, creg(s), 0b001)),
+
+        // C.SLLI
+        "000_0rrrrrsssss_10" => Some(shift_type(0b0000000, s as u32, r as u32, 0b001)),
+        // C.LWSP (rd = 0 は予約)
+        "010_arrrrrbbbcc_10" => {
+            let uimm: u8 = bitpack!("ccabbb00");
+            (r != 0).then(|| i_type(uimm as i32, 2, 0b010, r as u32, LOAD))
+        }
+        // C.JR (rs1 = 0 は予約)
+        "100_0rrrrr00000_10" => (r != 0).then(|| i_type(0, r as u32, 0b000, 0, JALR)),
+        // C.MV
+        "100_0rrrrrsssss_10" => Some(r_type(0b0000000, s as u32, 0, 0b000, r as u32)),
+        // C.EBREAK
+        "100_10000000000_10" => Some(EBREAK),
+        // C.JALR
+        "100_1rrrrr00000_10" => Some(i_type(0, r as u32, 0b000, 1, JALR)),
+        // C.ADD
+        "100_1rrrrrsssss_10" => Some(r_type(0b0000000, s as u32, r as u32, 0b000, r as u32)),
+        // C.SWSP
+        "110_aaaabbsssss_10" => {
+            let uimm: u8 = bitpack!("bbaaaa00");
+            Some(s_type(uimm as i32, s as u32, 2, 0b010))
+        }
+
+        _ => None,
+    }
+}
+
+// rs1', rs2', rd' はx8からx15を表す
+fn creg(reg: u16) -> u32 {
+    reg as u32 + 8
+}
+
+fn sext(value: impl Into<u32>, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    ((value.into() as i32) << shift) >> shift
+}
+
+// C.J/C.JALのoffset[11|4|9:8|10|6|7|3:1|5]
+#[bitmatch]
+fn cj_offset(bits: u16) -> i32 {
+    #[bitmatch]
+    let "abccdefggg_h" = bits;
+    let offset: u16 = bitpack!("adcc_fehb_ggg0");
+    sext(offset, 12)
+}
+
+// C.BEQZ/C.BNEZのoffset[8|4:3] と offset[7:6|2:1|5]
+#[bitmatch]
+fn cb_offset(high: u16, low: u16) -> i32 {
+    #[bitmatch]
+    let "abb" = high;
+    #[bitmatch]
+    let "ccdde" = low;
+    let offset: u16 = bitpack!("acce_bbdd0");
+    sext(offset, 9)
+}
+
+fn r_type(funct7: u32, rs2: u32, rs1: u32, funct3: u32, rd: u32) -> u32 {
+    funct7 << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | OP
+}
+
+fn shift_type(funct7: u32, shamt: u32, rd: u32, funct3: u32) -> u32 {
+    funct7 << 25 | shamt << 20 | rd << 15 | funct3 << 12 | rd << 7 | OP_IMM
+}
+
+fn i_type(imm: i32, rs1: u32, funct3: u32, rd: u32, opcode: u32) -> u32 {
+    ((imm as u32) & 0xfff) << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+}
+
+fn s_type(imm: i32, rs2: u32, rs1: u32, funct3: u32) -> u32 {
+    let imm = imm as u32;
+    (imm >> 5 & 0x7f) << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | (imm & 0x1f) << 7 | STORE
+}
+
+fn b_type(imm: i32, rs2: u32, rs1: u32, funct3: u32) -> u32 {
+    let imm = imm as u32;
+    (imm >> 12 & 1) << 31
+        | (imm >> 5 & 0x3f) << 25
+        | rs2 << 20
+        | rs1 << 15
+        | funct3 << 12
+        | (imm >> 1 & 0xf) << 8
+        | (imm >> 11 & 1) << 7
+        | BRANCH
+}
+
+fn j_type(imm: i32, rd: u32) -> u32 {
+    let imm = imm as u32;
+    (imm >> 20 & 1) << 31
+        | (imm >> 1 & 0x3ff) << 21
+        | (imm >> 11 & 1) << 20
+        | (imm >> 12 & 0xff) << 12
+        | rd << 7
+        | JAL
+}
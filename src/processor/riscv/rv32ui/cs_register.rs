@@ -1,25 +1,434 @@
 use std::{fmt::Display, ops::Add};
 
+use super::extension::{Extension, Extensions};
+use super::trap::Privilege;
+
 const REGISTERS_COUNT: usize = 4096;
 
+pub const SSTATUS: u32 = 0x100;
+pub const SIE: u32 = 0x104;
+pub const STVEC: u32 = 0x105;
+pub const SCOUNTEREN: u32 = 0x106;
+pub const SSCRATCH: u32 = 0x140;
+pub const SEPC: u32 = 0x141;
+pub const SCAUSE: u32 = 0x142;
+pub const STVAL: u32 = 0x143;
+pub const SIP: u32 = 0x144;
+pub const SATP: u32 = 0x180;
+
+pub const MSTATUS: u32 = 0x300;
+pub const MISA: u32 = 0x301;
+pub const MEDELEG: u32 = 0x302;
+pub const MIDELEG: u32 = 0x303;
+pub const MIE: u32 = 0x304;
+pub const MTVEC: u32 = 0x305;
+pub const MCOUNTEREN: u32 = 0x306;
+pub const MSTATUSH: u32 = 0x310;
+pub const MCOUNTINHIBIT: u32 = 0x320;
+pub const MSCRATCH: u32 = 0x340;
+pub const MEPC: u32 = 0x341;
+pub const MCAUSE: u32 = 0x342;
+pub const MTVAL: u32 = 0x343;
+pub const MIP: u32 = 0x344;
+
+pub const MCYCLE: u32 = 0xb00;
+pub const MINSTRET: u32 = 0xb02;
+pub const MCYCLEH: u32 = 0xb80;
+pub const MINSTRETH: u32 = 0xb82;
+pub const CYCLE: u32 = 0xc00;
+pub const TIME: u32 = 0xc01;
+pub const INSTRET: u32 = 0xc02;
+pub const CYCLEH: u32 = 0xc80;
+pub const TIMEH: u32 = 0xc81;
+pub const INSTRETH: u32 = 0xc82;
+
+pub const MVENDORID: u32 = 0xf11;
+pub const MARCHID: u32 = 0xf12;
+pub const MIMPID: u32 = 0xf13;
+pub const MHARTID: u32 = 0xf14;
+
+// 名前の付いたCSR。逆アセンブラやデバッガでの表示に使う
+pub const CSR_NAMES: [(u32, &str); 38] = [
+    (SSTATUS, "sstatus"),
+    (SIE, "sie"),
+    (STVEC, "stvec"),
+    (SCOUNTEREN, "scounteren"),
+    (SSCRATCH, "sscratch"),
+    (SEPC, "sepc"),
+    (SCAUSE, "scause"),
+    (STVAL, "stval"),
+    (SIP, "sip"),
+    (SATP, "satp"),
+    (MSTATUS, "mstatus"),
+    (MISA, "misa"),
+    (MEDELEG, "medeleg"),
+    (MIDELEG, "mideleg"),
+    (MIE, "mie"),
+    (MTVEC, "mtvec"),
+    (MCOUNTEREN, "mcounteren"),
+    (MSTATUSH, "mstatush"),
+    (MCOUNTINHIBIT, "mcountinhibit"),
+    (MSCRATCH, "mscratch"),
+    (MEPC, "mepc"),
+    (MCAUSE, "mcause"),
+    (MTVAL, "mtval"),
+    (MIP, "mip"),
+    (MCYCLE, "mcycle"),
+    (MINSTRET, "minstret"),
+    (MCYCLEH, "mcycleh"),
+    (MINSTRETH, "minstreth"),
+    (CYCLE, "cycle"),
+    (TIME, "time"),
+    (INSTRET, "instret"),
+    (CYCLEH, "cycleh"),
+    (TIMEH, "timeh"),
+    (INSTRETH, "instreth"),
+    (MVENDORID, "mvendorid"),
+    (MARCHID, "marchid"),
+    (MIMPID, "mimpid"),
+    (MHARTID, "mhartid"),
+];
+
+pub fn csr_name(index: u32) -> Option<&'static str> {
+    CSR_NAMES
+        .iter()
+        .find(|&&(csr, _)| csr == index)
+        .map(|&(_, name)| name)
+}
+
+pub const MSTATUS_SIE: u32 = 1 << 1;
+pub const MSTATUS_MIE: u32 = 1 << 3;
+pub const MSTATUS_SPIE: u32 = 1 << 5;
+pub const MSTATUS_MPIE: u32 = 1 << 7;
+pub const MSTATUS_SPP_SHIFT: u32 = 8;
+pub const MSTATUS_SPP: u32 = 1 << MSTATUS_SPP_SHIFT;
+pub const MSTATUS_MPP_SHIFT: u32 = 11;
+pub const MSTATUS_MPP: u32 = 0b11 << MSTATUS_MPP_SHIFT;
+pub const MSTATUS_MPRV: u32 = 1 << 17;
+pub const MSTATUS_SUM: u32 = 1 << 18;
+pub const MSTATUS_MXR: u32 = 1 << 19;
+pub const MSTATUS_TVM: u32 = 1 << 20;
+pub const MSTATUS_TW: u32 = 1 << 21;
+pub const MSTATUS_TSR: u32 = 1 << 22;
+
+pub const MIP_SSIP: u32 = 1 << 1;
+pub const MIP_MSIP: u32 = 1 << 3;
+pub const MIP_STIP: u32 = 1 << 5;
+pub const MIP_MTIP: u32 = 1 << 7;
+pub const MIP_SEIP: u32 = 1 << 9;
+pub const MIP_MEIP: u32 = 1 << 11;
+
+// 書き込めるmstatusのビット。FS/XS/SDなどは実装していないので常に0
+const MSTATUS_MASK: u32 = MSTATUS_SIE
+    | MSTATUS_MIE
+    | MSTATUS_SPIE
+    | MSTATUS_MPIE
+    | MSTATUS_SPP
+    | MSTATUS_MPP
+    | MSTATUS_MPRV
+    | MSTATUS_SUM
+    | MSTATUS_MXR
+    | MSTATUS_TVM
+    | MSTATUS_TW
+    | MSTATUS_TSR;
+
+// sstatusから見えるmstatusのビット
+const SSTATUS_MASK: u32 = MSTATUS_SIE | MSTATUS_SPIE | MSTATUS_SPP | MSTATUS_SUM | MSTATUS_MXR;
+
+// Mモードからのecall(11)と予約済みの例外は委譲できない
+const MEDELEG_MASK: u32 = 0xb3ff;
+// Sモードの割り込み(SSIP/STIP/SEIP)のみ委譲できる
+const MIDELEG_MASK: u32 = MIP_SSIP | MIP_STIP | MIP_SEIP;
+const MIE_MASK: u32 = MIDELEG_MASK | MIP_MSIP | MIP_MTIP | MIP_MEIP;
+// mipのうちソフトウェアから書けるのはSモードの割り込みのみ
+const MIP_MASK: u32 = MIP_SSIP | MIP_STIP | MIP_SEIP;
+
+// mcountinhibitとmcounteren/scounterenのビット
+pub const COUNTER_CY: u32 = 1 << 0;
+pub const COUNTER_IR: u32 = 1 << 2;
+// hpmcounterは実装していないのでCYとIRのみ止められる
+const MCOUNTINHIBIT_MASK: u32 = COUNTER_CY | COUNTER_IR;
+
+const TVEC_MODE_MASK: u32 = 0b11;
+
+const MISA_MXL_32: u32 = 1 << 30;
+
 #[derive(Debug, Clone, Copy)]
 pub struct ControlAndStatusRegister {
-    csregs: [u32; REGISTERS_COUNT],
+    misa: u32,
+    mstatus: u32,
+    medeleg: u32,
+    mideleg: u32,
+    mie: u32,
+    mip: u32,
+    // デバイスから入ってくる割り込み線。mipを読むとソフトウェアが書いたビットとORされる
+    interrupt_lines: u32,
+    // トラップハンドラが設定されるまではNone
+    mtvec: Option<u32>,
+    mcounteren: u32,
+    mcountinhibit: u32,
+    mscratch: u32,
+    mepc: u32,
+    mcause: u32,
+    mtval: u32,
+
+    stvec: Option<u32>,
+    scounteren: u32,
+    sscratch: u32,
+    sepc: u32,
+    scause: u32,
+    stval: u32,
+    satp: u32,
+
+    mcycle: u64,
+    minstret: u64,
+    time: u64,
+    // この命令でCSR命令が書き込んだカウンタ(COUNTER_CY/COUNTER_IR)
+    written_counters: u32,
+}
+
+impl Default for ControlAndStatusRegister {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ControlAndStatusRegister {
     pub fn new() -> Self {
-        let xregs = [0u32; REGISTERS_COUNT];
+        Self::with_extensions(Extensions::rv32i())
+    }
+
+    pub fn with_extensions(extensions: Extensions) -> Self {
+        Self {
+            misa: misa(&extensions),
+            mstatus: 0,
+            medeleg: 0,
+            mideleg: 0,
+            mie: 0,
+            mip: 0,
+            interrupt_lines: 0,
+            mtvec: None,
+            mcounteren: 0,
+            mcountinhibit: 0,
+            mscratch: 0,
+            mepc: 0,
+            mcause: 0,
+            mtval: 0,
+            stvec: None,
+            scounteren: 0,
+            sscratch: 0,
+            sepc: 0,
+            scause: 0,
+            stval: 0,
+            satp: 0,
+            mcycle: 0,
+            minstret: 0,
+            time: 0,
+            written_counters: 0,
+        }
+    }
+
+    // 命令を1つ実行するたびに呼ぶ。retiredは命令が完了したかどうか
+    // CSR命令で書き込まれたカウンタはその値を優先し、この命令では進めない
+    pub fn count(&mut self, retired: bool) {
+        let stopped = self.mcountinhibit | self.written_counters;
+        self.written_counters = 0;
+
+        if stopped & COUNTER_CY == 0 {
+            self.mcycle = self.mcycle.wrapping_add(1);
+        }
+        if retired && stopped & COUNTER_IR == 0 {
+            self.minstret = self.minstret.wrapping_add(1);
+        }
+    }
+
+    // CLINTやPLICから来る割り込み線の状態をmipに反映する
+    pub fn set_interrupt_pending(&mut self, bit: u32, pending: bool) {
+        if pending {
+            self.interrupt_lines |= bit;
+        } else {
+            self.interrupt_lines &= !bit;
+        }
+    }
 
-        Self { csregs: xregs }
+    // トラップハンドラのアドレス。ソフトウェアがmtvec/stvecを書き込むまではNone
+    pub fn mtvec(&self) -> Option<u32> {
+        self.mtvec
     }
 
+    pub fn stvec(&self) -> Option<u32> {
+        self.stvec
+    }
+
+    // timeはタイマーのmtimeを映す
+    pub fn set_time(&mut self, time: u64) {
+        self.time = time;
+    }
+
+    // 実装していないCSRは0を返す
     pub fn read(&self, index: u32) -> u32 {
-        self.csregs[index as usize]
+        self.get(index).unwrap_or(0)
     }
 
+    // WARLのマスクをかけて書き込む。読み取り専用や実装していないCSRへの書き込みは無視する
     pub fn write(&mut self, index: u32, value: u32) {
-        self.csregs[index as usize] = value;
+        match index {
+            SSTATUS => self.mstatus = self.mstatus & !SSTATUS_MASK | value & SSTATUS_MASK,
+            SIE => self.mie = self.mie & !self.mideleg | value & self.mideleg,
+            STVEC => self.stvec = Some(warl_tvec(self.stvec.unwrap_or(0), value)),
+            SCOUNTEREN => self.scounteren = value,
+            SSCRATCH => self.sscratch = value,
+            SEPC => self.sepc = value & !1,
+            SCAUSE => self.scause = value,
+            STVAL => self.stval = value,
+            SIP => {
+                let mask = self.mideleg & MIP_MASK;
+                self.mip = self.mip & !mask | value & mask;
+            }
+            SATP => self.satp = value,
+
+            MSTATUS => {
+                // MPPに予約済みの値(2)が書かれたら元の値のままにする
+                let value = if value & MSTATUS_MPP == 0b10 << MSTATUS_MPP_SHIFT {
+                    value & !MSTATUS_MPP | self.mstatus & MSTATUS_MPP
+                } else {
+                    value
+                };
+                self.mstatus = value & MSTATUS_MASK;
+            }
+            MEDELEG => self.medeleg = value & MEDELEG_MASK,
+            MIDELEG => self.mideleg = value & MIDELEG_MASK,
+            MIE => self.mie = value & MIE_MASK,
+            MTVEC => self.mtvec = Some(warl_tvec(self.mtvec.unwrap_or(0), value)),
+            MCOUNTEREN => self.mcounteren = value,
+            MCOUNTINHIBIT => self.mcountinhibit = value & MCOUNTINHIBIT_MASK,
+            MSCRATCH => self.mscratch = value,
+            MEPC => self.mepc = value & !1,
+            MCAUSE => self.mcause = value,
+            MTVAL => self.mtval = value,
+            MIP => self.mip = self.mip & !MIP_MASK | value & MIP_MASK,
+
+            MCYCLE => {
+                self.mcycle = self.mcycle & !0xffff_ffff | value as u64;
+                self.written_counters |= COUNTER_CY;
+            }
+            MCYCLEH => {
+                self.mcycle = self.mcycle & 0xffff_ffff | (value as u64) << 32;
+                self.written_counters |= COUNTER_CY;
+            }
+            MINSTRET => {
+                self.minstret = self.minstret & !0xffff_ffff | value as u64;
+                self.written_counters |= COUNTER_IR;
+            }
+            MINSTRETH => {
+                self.minstret = self.minstret & 0xffff_ffff | (value as u64) << 32;
+                self.written_counters |= COUNTER_IR;
+            }
+
+            _ => (),
+        }
+    }
+
+    // CSR命令でアクセスできるかどうか
+    // csr[11:10]が11なら読み取り専用、csr[9:8]はアクセスに必要な最低の特権レベル
+    pub fn is_accessible(&self, index: u32, privilege: Privilege, write: bool) -> bool {
+        let read_only = index >> 10 & 0b11 == 0b11;
+        let required_privilege = index >> 8 & 0b11;
+
+        self.get(index).is_some()
+            && privilege as u32 >= required_privilege
+            && !(write && read_only)
+            && self.is_counter_enabled(index, privilege)
+    }
+
+    // cycle/time/instret/hpmcounterはmcounteren(Sモード)とscounteren(Uモード)で許可する
+    fn is_counter_enabled(&self, index: u32, privilege: Privilege) -> bool {
+        if !matches!(index, 0xc00..=0xc1f | 0xc80..=0xc9f) {
+            return true;
+        }
+
+        let bit = 1 << (index & 0x1f);
+        match privilege {
+            Privilege::Machine => true,
+            Privilege::Supervisor => self.mcounteren & bit != 0,
+            Privilege::User => self.mcounteren & self.scounteren & bit != 0,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<u32> {
+        let value = match index {
+            SSTATUS => self.mstatus & SSTATUS_MASK,
+            SIE => self.mie & self.mideleg,
+            STVEC => self.stvec.unwrap_or(0),
+            SCOUNTEREN => self.scounteren,
+            SSCRATCH => self.sscratch,
+            SEPC => self.sepc,
+            SCAUSE => self.scause,
+            STVAL => self.stval,
+            SIP => (self.mip | self.interrupt_lines) & self.mideleg,
+            SATP => self.satp,
+
+            MSTATUS => self.mstatus,
+            MISA => self.misa,
+            MEDELEG => self.medeleg,
+            MIDELEG => self.mideleg,
+            MIE => self.mie,
+            MTVEC => self.mtvec.unwrap_or(0),
+            MCOUNTEREN => self.mcounteren,
+            MSTATUSH => 0,
+            MCOUNTINHIBIT => self.mcountinhibit,
+            MSCRATCH => self.mscratch,
+            MEPC => self.mepc,
+            MCAUSE => self.mcause,
+            MTVAL => self.mtval,
+            MIP => self.mip | self.interrupt_lines,
+
+            // PMPは0エントリとして実装する(読むと0、書き込みは無視)
+            0x3a0..=0x3a3 | 0x3b0..=0x3bf => 0,
+            // mhpmevent3-31とhpmcounter3-31は常に0
+            0x323..=0x33f | 0xb03..=0xb1f | 0xb83..=0xb9f => 0,
+            0xc03..=0xc1f | 0xc83..=0xc9f => 0,
+
+            MCYCLE | CYCLE => self.mcycle as u32,
+            MCYCLEH | CYCLEH => (self.mcycle >> 32) as u32,
+            MINSTRET | INSTRET => self.minstret as u32,
+            MINSTRETH | INSTRETH => (self.minstret >> 32) as u32,
+            TIME => self.time as u32,
+            TIMEH => (self.time >> 32) as u32,
+
+            MVENDORID | MARCHID | MIMPID | MHARTID => 0,
+
+            _ => return None,
+        };
+
+        Some(value)
+    }
+}
+
+// misaの下位26bitは拡張をアルファベット順に表す(A=bit0, B=bit1, ...)
+fn misa(extensions: &Extensions) -> u32 {
+    let letter = |c: char| 1 << (c as u32 - 'a' as u32);
+
+    let mut misa = MISA_MXL_32 | letter('i') | letter('s') | letter('u');
+    for (extension, c) in [
+        (Extension::M, 'm'),
+        (Extension::A, 'a'),
+        (Extension::C, 'c'),
+    ] {
+        if extensions.contains(extension) {
+            misa |= letter(c);
+        }
+    }
+
+    misa
+}
+
+// xtvecのMODEは0(Direct)と1(Vectored)のみ。それ以外が書かれたら元のMODEのままにする
+fn warl_tvec(old: u32, value: u32) -> u32 {
+    if value & TVEC_MODE_MASK > 1 {
+        value & !TVEC_MODE_MASK | old & TVEC_MODE_MASK
+    } else {
+        value
     }
 }
 
@@ -27,11 +436,10 @@ impl Display for ControlAndStatusRegister {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut res = String::new();
         for i in 0..REGISTERS_COUNT {
-            let s = format!(
-                "\x1b[38;5;4m{:0>4}:\x1b[m 0x{:x} \n",
-                i,
-                self.read(i as u32)
-            );
+            let Some(value) = self.get(i as u32) else {
+                continue;
+            };
+            let s = format!("\x1b[38;5;4m{:0>4x}:\x1b[m 0x{:x} \n", i, value);
             res = res.add(&s);
         }
         write!(f, "{}", res)
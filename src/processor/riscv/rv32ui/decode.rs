@@ -1,48 +1,22 @@
-use std::fmt::Display;
-
 use bitmatch::bitmatch;
 use bitvec::{bitvec, field::BitField, prelude::Lsb0, view::BitView};
 
-use crate::processor::ProcessorError;
-use crate::processor::ProcessorErrorTrait;
-
+use super::compressed;
+use super::extension::{Extension, Extensions};
+use super::trap::Exception;
 use super::x_register::XRegisters;
 
-pub enum DecodeErrorType {
-    NotMatchOpcode,
-}
-
-pub struct DecodeError {
-    error_type: DecodeErrorType,
-}
-
-impl DecodeError {
-    fn new(error_type: DecodeErrorType) -> Box<Self> {
-        Box::new(DecodeError { error_type })
-    }
-}
-
-impl ProcessorErrorTrait for DecodeError {}
-
-impl Display for DecodeError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match &self.error_type {
-            DecodeErrorType::NotMatchOpcode => write!(f, "Not match opcode."),
-        }
-    }
-}
-
 #[deny(non_camel_case_types)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Opcode {
-    LB,  // todo
-    LH,  // todo
+    LB,  // Ok
+    LH,  // Ok
     LW,  // Ok
-    LBU, // todo
-    LHU, // todo
+    LBU, // Ok
+    LHU, // Ok
 
-    SB, // todo
-    SH, // todo
+    SB, // Ok
+    SH, // Ok
     SW, // Ok
 
     ADD,  // Ok
@@ -82,6 +56,27 @@ pub enum Opcode {
     LUI,   // Ok
     AUIPC, // Ok
 
+    MUL,    // Ok
+    MULH,   // Ok
+    MULHSU, // Ok
+    MULHU,  // Ok
+    DIV,    // Ok
+    DIVU,   // Ok
+    REM,    // Ok
+    REMU,   // Ok
+
+    LRW,      // Ok
+    SCW,      // Ok
+    AMOSWAPW, // Ok
+    AMOADDW,  // Ok
+    AMOXORW,  // Ok
+    AMOANDW,  // Ok
+    AMOORW,   // Ok
+    AMOMINW,  // Ok
+    AMOMAXW,  // Ok
+    AMOMINUW, // Ok
+    AMOMAXUW, // Ok
+
     CSRRW,
     CSRRWI,
     CSRRS,
@@ -90,23 +85,54 @@ pub enum Opcode {
     CSRRCI,
 
     URET, // todo
-    SRET, // todo
-    MRET, // todo
+    SRET, // Ok
+    MRET, // Ok
 
-    WFI, // todo
+    WFI, // Ok
 
     ECALL,
-    EBREAK, // todo
+    EBREAK, // Ok
 
     FENCE,     // todo
-    FENCEI,    // todo
-    SFENCEVMA, //todo
+    FENCEI,    // Ok
+    SFENCEVMA, // Ok
+}
+
+impl Opcode {
+    pub fn extension(&self) -> Extension {
+        match self {
+            Opcode::MUL
+            | Opcode::MULH
+            | Opcode::MULHSU
+            | Opcode::MULHU
+            | Opcode::DIV
+            | Opcode::DIVU
+            | Opcode::REM
+            | Opcode::REMU => Extension::M,
+            Opcode::LRW
+            | Opcode::SCW
+            | Opcode::AMOSWAPW
+            | Opcode::AMOADDW
+            | Opcode::AMOXORW
+            | Opcode::AMOANDW
+            | Opcode::AMOORW
+            | Opcode::AMOMINW
+            | Opcode::AMOMAXW
+            | Opcode::AMOMINUW
+            | Opcode::AMOMAXUW => Extension::A,
+            _ => Extension::I,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct DecodeResult {
     pub opcode: Opcode,
+    // 命令長(RVCなら2、それ以外は4)
+    pub inst_len: u32,
 
+    pub rs1: u32,
+    pub rs2: u32,
     pub rs1_data: u32,
     pub rs2_data: u32,
     pub rd: u32,
@@ -131,10 +157,88 @@ pub struct DecodeResult {
     pub csr: u32,
 }
 
+impl DecodeResult {
+    pub fn is_csr(&self) -> bool {
+        matches!(
+            self.opcode,
+            Opcode::CSRRW
+                | Opcode::CSRRWI
+                | Opcode::CSRRS
+                | Opcode::CSRRSI
+                | Opcode::CSRRC
+                | Opcode::CSRRCI
+        )
+    }
+
+    // CSRRS/CSRRCはrs1がx0、CSRRSI/CSRRCIはuimmが0ならCSRに書き込まない
+    // rs1とuimmは同じビット位置なのでどちらもimm_zで判定できる
+    pub fn writes_csr(&self) -> bool {
+        match self.opcode {
+            Opcode::CSRRW | Opcode::CSRRWI => true,
+            Opcode::CSRRS | Opcode::CSRRSI | Opcode::CSRRC | Opcode::CSRRCI => self.imm_z != 0,
+            _ => false,
+        }
+    }
+
+    // rdに結果を書き込む命令かどうか。x0への書き込みは含めない
+    pub fn writes_rd(&self) -> bool {
+        let no_rd = matches!(
+            self.opcode,
+            Opcode::SB
+                | Opcode::SH
+                | Opcode::SW
+                | Opcode::BEQ
+                | Opcode::BNE
+                | Opcode::BLT
+                | Opcode::BGE
+                | Opcode::BLTU
+                | Opcode::BGEU
+                | Opcode::URET
+                | Opcode::SRET
+                | Opcode::MRET
+                | Opcode::WFI
+                | Opcode::ECALL
+                | Opcode::EBREAK
+                | Opcode::FENCE
+                | Opcode::FENCEI
+                | Opcode::SFENCEVMA
+        );
+
+        !no_rd && self.rd != 0
+    }
+}
+
 pub struct Decode();
 
 impl Decode {
-    pub fn decode(&self, inst: u32, xregs: &XRegisters) -> Result<DecodeResult, ProcessorError> {
+    pub fn decode(
+        &self,
+        inst: u32,
+        xregs: &XRegisters,
+        extensions: &Extensions,
+    ) -> Result<DecodeResult, Exception> {
+        // 不正な命令はmtvalに命令のビット列を入れて例外にする
+        let illegal_instruction = Exception::IllegalInstruction(inst);
+
+        // RVC命令は32bit命令に展開してから解釈する
+        let (inst, inst_len) = if compressed::is_compressed(inst) {
+            let Some(expanded) =
+                compressed::expand(inst as u16).filter(|_| extensions.contains(Extension::C))
+            else {
+                return Err(illegal_instruction);
+            };
+            crate::trace!(
+                Decode,
+                Debug,
+                "Decode: compressed 0x{:0>4x} -> 0x{:0>8x}",
+                inst,
+                expanded
+            );
+            (expanded, 2)
+        } else {
+            (inst, 4)
+        };
+
         let inst_slice = inst.view_bits::<Lsb0>();
 
         let rs1_addr = inst_slice[15..=19].load::<u32>(); // R, I, S, B type
@@ -179,25 +283,40 @@ impl Decode {
 
         let csr = inst_slice[20..=31].load::<u32>();
 
-        if let Some(opcode) = self.match_opcode(inst) {
-            println!("Decode: opcode \x1b[38;5;2m{:?}\x1b[m", opcode);
-            println!(
+        let opcode = self
+            .match_opcode(inst)
+            .filter(|opcode| extensions.contains(opcode.extension()));
+
+        if let Some(opcode) = opcode {
+            crate::trace!(
+                Decode,
+                Trace,
                 "        rs1_addr: 0b{:0>5b}({}),    rs2_addr: 0b{:0>5b}({}), rd(wb_addr): 0b{:0>5b}({})",
                 rs1_addr, rs1_addr, rs2_addr, rs2_addr, rd, rd
             );
 
-            println!(
+            crate::trace!(
+                Decode,
+                Trace,
                 "        rs1_data: 0x{:0>8x}({}), rs2_data: 0x{:0>8x}({})",
-                rs1_data, rs1_data, rs2_data, rs2_data
+                rs1_data,
+                rs1_data,
+                rs2_data,
+                rs2_data
             );
 
-            println!(
+            crate::trace!(
+                Decode,
+                Trace,
                 "        imm_i: 0x{:0>8x}({}), imm_s: 0x{:0>8x}({}), imm_b: 0x{:0>8x}({}), imm_j: 0x{:0>8x}({}), imm_u: 0x{:0>8x}({}), imm_z: 0x{:0>8x}({}),",
                 imm_i, imm_i, imm_s, imm_s, imm_b, imm_b, imm_j, imm_j, imm_u, imm_u, imm_z, imm_z,
             );
 
             Ok(DecodeResult {
                 opcode,
+                inst_len,
+                rs1: rs1_addr,
+                rs2: rs2_addr,
                 rs1_data,
                 rs2_data,
                 rd,
@@ -215,7 +334,7 @@ impl Decode {
                 csr,
             })
         } else {
-            Err(DecodeError::new(DecodeErrorType::NotMatchOpcode))
+            Err(illegal_instruction)
         }
     }
 
@@ -225,7 +344,14 @@ impl Decode {
     fn match_opcode(&self, inst: u32) -> Option<Opcode> {
         #[bitmatch]
         match inst {
+            "?????????????????000?????0000011" => Some(Opcode::LB),
+            "?????????????????001?????0000011" => Some(Opcode::LH),
             "?????????????????010?????0000011" => Some(Opcode::LW),
+            "?????????????????100?????0000011" => Some(Opcode::LBU),
+            "?????????????????101?????0000011" => Some(Opcode::LHU),
+
+            "?????????????????000?????0100011" => Some(Opcode::SB),
+            "?????????????????001?????0100011" => Some(Opcode::SH),
             "?????????????????010?????0100011" => Some(Opcode::SW),
 
             "0000000??????????000?????0110011" => Some(Opcode::ADD),
@@ -265,6 +391,27 @@ impl Decode {
             "?????????????????????????0110111" => Some(Opcode::LUI),
             "?????????????????????????0010111" => Some(Opcode::AUIPC),
 
+            "0000001??????????000?????0110011" => Some(Opcode::MUL),
+            "0000001??????????001?????0110011" => Some(Opcode::MULH),
+            "0000001??????????010?????0110011" => Some(Opcode::MULHSU),
+            "0000001??????????011?????0110011" => Some(Opcode::MULHU),
+            "0000001??????????100?????0110011" => Some(Opcode::DIV),
+            "0000001??????????101?????0110011" => Some(Opcode::DIVU),
+            "0000001??????????110?????0110011" => Some(Opcode::REM),
+            "0000001??????????111?????0110011" => Some(Opcode::REMU),
+
+            "00010??00000?????010?????0101111" => Some(Opcode::LRW),
+            "00011????????????010?????0101111" => Some(Opcode::SCW),
+            "00001????????????010?????0101111" => Some(Opcode::AMOSWAPW),
+            "00000????????????010?????0101111" => Some(Opcode::AMOADDW),
+            "00100????????????010?????0101111" => Some(Opcode::AMOXORW),
+            "01100????????????010?????0101111" => Some(Opcode::AMOANDW),
+            "01000????????????010?????0101111" => Some(Opcode::AMOORW),
+            "10000????????????010?????0101111" => Some(Opcode::AMOMINW),
+            "10100????????????010?????0101111" => Some(Opcode::AMOMAXW),
+            "11000????????????010?????0101111" => Some(Opcode::AMOMINUW),
+            "11100????????????010?????0101111" => Some(Opcode::AMOMAXUW),
+
             "?????????????????001?????1110011" => Some(Opcode::CSRRW),
             "?????????????????101?????1110011" => Some(Opcode::CSRRWI),
             "?????????????????010?????1110011" => Some(Opcode::CSRRS),
@@ -273,10 +420,15 @@ impl Decode {
             "?????????????????111?????1110011" => Some(Opcode::CSRRCI),
 
             "00000000000000000000000001110011" => Some(Opcode::ECALL),
+            "00000000000100000000000001110011" => Some(Opcode::EBREAK),
 
+            "00010000001000000000000001110011" => Some(Opcode::SRET),
             "00110000001000000000000001110011" => Some(Opcode::MRET),
+            "00010000010100000000000001110011" => Some(Opcode::WFI),
+            "0001001??????????000000001110011" => Some(Opcode::SFENCEVMA),
 
             "0000????????00000000000000001111" => Some(Opcode::FENCE),
+            "?????????????????001?????0001111" => Some(Opcode::FENCEI),
 
             _ => None,
         }
@@ -0,0 +1,269 @@
+use std::fmt::Display;
+
+use super::compressed;
+use super::cs_register::csr_name;
+use super::decode::{Decode, DecodeResult, Opcode};
+use super::extension::Extensions;
+use super::x_register::{abi_name, XRegisters};
+
+use crate::elf::{Elf, ElfError};
+
+// 逆アセンブルした1命令。RVC命令のinstは展開する前の16bit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disassembly {
+    pub address: u32,
+    pub inst: u32,
+    pub inst_len: u32,
+    pub text: String,
+}
+
+// "80000000: 0500006f  jal zero, 0x80000050" の形式で表示する
+impl Display for Disassembly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let inst = if self.inst_len == 2 {
+            format!("{:0>4x}", self.inst)
+        } else {
+            format!("{:0>8x}", self.inst)
+        };
+        write!(f, "{:0>8x}: {:<8}  {}", self.address, inst, self.text)
+    }
+}
+
+// pcにあるinstをアセンブリの文字列にする。分岐先はpcからの絶対アドレスで表示する
+// 全ての拡張を有効にして解釈し、解釈できない命令は.half/.wordとして表示する
+pub fn disassemble(inst: u32, pc: u32) -> String {
+    match Decode().decode(inst, &XRegisters::new(), &Extensions::all()) {
+        Ok(decode) => format_instruction(&decode, pc),
+        Err(_) if compressed::is_compressed(inst) => format!(".half 0x{:0>4x}", inst as u16),
+        Err(_) => format!(".word 0x{:0>8x}", inst),
+    }
+}
+
+// addressから始まる機械語の列を先頭から順に逆アセンブルする
+// 末尾に命令の途中までしか無ければそこで止める
+pub fn disassemble_bytes(data: &[u8], address: u32) -> Vec<Disassembly> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while let Some(low) = data.get(offset..offset + 2) {
+        let low = u16::from_le_bytes([low[0], low[1]]) as u32;
+        let (inst, inst_len) = if compressed::is_compressed(low) {
+            (low, 2)
+        } else {
+            let Some(high) = data.get(offset + 2..offset + 4) else {
+                break;
+            };
+            let high = u16::from_le_bytes([high[0], high[1]]) as u32;
+            (high << 16 | low, 4)
+        };
+
+        let pc = address.wrapping_add(offset as u32);
+        lines.push(Disassembly {
+            address: pc,
+            inst,
+            inst_len,
+            text: disassemble(inst, pc),
+        });
+        offset += inst_len as usize;
+    }
+
+    lines
+}
+
+// ELFの.textなど実行可能なセクションをそれぞれ逆アセンブルし、セクション名と組にして返す
+pub fn disassemble_text(elf: &Elf) -> Result<Vec<(String, Vec<Disassembly>)>, ElfError> {
+    let mut sections = Vec::new();
+    for header in elf.executable_sections() {
+        let lines = disassemble_bytes(elf.section_data(header)?, header.addr);
+        sections.push((elf.section_name(header)?, lines));
+    }
+
+    if sections.is_empty() {
+        return Err(ElfError::NoExecutableSection);
+    }
+
+    Ok(sections)
+}
+
+// デコード済みの命令をアセンブリの文字列にする
+pub fn format_instruction(decode: &DecodeResult, pc: u32) -> String {
+    let name = mnemonic(decode.opcode);
+    let rd = abi_name(decode.rd);
+    let rs1 = abi_name(decode.rs1);
+    let rs2 = abi_name(decode.rs2);
+    let csr = || match csr_name(decode.csr) {
+        Some(name) => name.to_string(),
+        None => format!("0x{:0>3x}", decode.csr),
+    };
+
+    match decode.opcode {
+        Opcode::LB | Opcode::LH | Opcode::LW | Opcode::LBU | Opcode::LHU => {
+            format!("{} {}, {}({})", name, rd, decode.imm_i_sext, rs1)
+        }
+        Opcode::SB | Opcode::SH | Opcode::SW => {
+            format!("{} {}, {}({})", name, rs2, decode.imm_s_sext, rs1)
+        }
+
+        Opcode::ADDI | Opcode::ANDI | Opcode::ORI | Opcode::XORI | Opcode::SLTI | Opcode::SLTIU => {
+            format!("{} {}, {}, {}", name, rd, rs1, decode.imm_i_sext)
+        }
+        Opcode::SLLI | Opcode::SRLI | Opcode::SRAI => {
+            format!("{} {}, {}, {}", name, rd, rs1, decode.imm_i & 0x1f)
+        }
+
+        Opcode::BEQ | Opcode::BNE | Opcode::BLT | Opcode::BGE | Opcode::BLTU | Opcode::BGEU => {
+            let target = pc.wrapping_add(decode.imm_b_sext as u32);
+            format!("{} {}, {}, 0x{:0>8x}", name, rs1, rs2, target)
+        }
+        Opcode::JAL => {
+            let target = pc.wrapping_add(decode.imm_j_sext as u32);
+            format!("{} {}, 0x{:0>8x}", name, rd, target)
+        }
+        Opcode::JALR => format!("{} {}, {}({})", name, rd, decode.imm_i_sext, rs1),
+
+        Opcode::LUI | Opcode::AUIPC => format!("{} {}, 0x{:x}", name, rd, decode.imm_u),
+
+        // aq/rlはfunct5の下の2bitで、imm_iの6bit目と5bit目にあたる
+        Opcode::LRW => format!("{}{} {}, ({})", name, ordering(decode), rd, rs1),
+        Opcode::SCW
+        | Opcode::AMOSWAPW
+        | Opcode::AMOADDW
+        | Opcode::AMOXORW
+        | Opcode::AMOANDW
+        | Opcode::AMOORW
+        | Opcode::AMOMINW
+        | Opcode::AMOMAXW
+        | Opcode::AMOMINUW
+        | Opcode::AMOMAXUW => {
+            format!("{}{} {}, {}, ({})", name, ordering(decode), rd, rs2, rs1)
+        }
+
+        Opcode::CSRRW | Opcode::CSRRS | Opcode::CSRRC => {
+            format!("{} {}, {}, {}", name, rd, csr(), rs1)
+        }
+        Opcode::CSRRWI | Opcode::CSRRSI | Opcode::CSRRCI => {
+            format!("{} {}, {}, {}", name, rd, csr(), decode.imm_z)
+        }
+
+        // predとsuccはimm_iの上位と下位の4bit
+        Opcode::FENCE => format!(
+            "{} {}, {}",
+            name,
+            fence_set(decode.imm_i >> 4),
+            fence_set(decode.imm_i)
+        ),
+        Opcode::SFENCEVMA => format!("{} {}, {}", name, rs1, rs2),
+
+        Opcode::URET
+        | Opcode::SRET
+        | Opcode::MRET
+        | Opcode::WFI
+        | Opcode::ECALL
+        | Opcode::EBREAK
+        | Opcode::FENCEI => name.to_string(),
+
+        // 残りはR形式
+        _ => format!("{} {}, {}, {}", name, rd, rs1, rs2),
+    }
+}
+
+fn mnemonic(opcode: Opcode) -> &'static str {
+    match opcode {
+        Opcode::LB => "lb",
+        Opcode::LH => "lh",
+        Opcode::LW => "lw",
+        Opcode::LBU => "lbu",
+        Opcode::LHU => "lhu",
+        Opcode::SB => "sb",
+        Opcode::SH => "sh",
+        Opcode::SW => "sw",
+        Opcode::ADD => "add",
+        Opcode::ADDI => "addi",
+        Opcode::SUB => "sub",
+        Opcode::AND => "and",
+        Opcode::OR => "or",
+        Opcode::XOR => "xor",
+        Opcode::ANDI => "andi",
+        Opcode::ORI => "ori",
+        Opcode::XORI => "xori",
+        Opcode::SLL => "sll",
+        Opcode::SRL => "srl",
+        Opcode::SRA => "sra",
+        Opcode::SLLI => "slli",
+        Opcode::SRLI => "srli",
+        Opcode::SRAI => "srai",
+        Opcode::SLT => "slt",
+        Opcode::SLTU => "sltu",
+        Opcode::SLTI => "slti",
+        Opcode::SLTIU => "sltiu",
+        Opcode::BEQ => "beq",
+        Opcode::BNE => "bne",
+        Opcode::BLT => "blt",
+        Opcode::BGE => "bge",
+        Opcode::BLTU => "bltu",
+        Opcode::BGEU => "bgeu",
+        Opcode::JAL => "jal",
+        Opcode::JALR => "jalr",
+        Opcode::LUI => "lui",
+        Opcode::AUIPC => "auipc",
+        Opcode::MUL => "mul",
+        Opcode::MULH => "mulh",
+        Opcode::MULHSU => "mulhsu",
+        Opcode::MULHU => "mulhu",
+        Opcode::DIV => "div",
+        Opcode::DIVU => "divu",
+        Opcode::REM => "rem",
+        Opcode::REMU => "remu",
+        Opcode::LRW => "lr.w",
+        Opcode::SCW => "sc.w",
+        Opcode::AMOSWAPW => "amoswap.w",
+        Opcode::AMOADDW => "amoadd.w",
+        Opcode::AMOXORW => "amoxor.w",
+        Opcode::AMOANDW => "amoand.w",
+        Opcode::AMOORW => "amoor.w",
+        Opcode::AMOMINW => "amomin.w",
+        Opcode::AMOMAXW => "amomax.w",
+        Opcode::AMOMINUW => "amominu.w",
+        Opcode::AMOMAXUW => "amomaxu.w",
+        Opcode::CSRRW => "csrrw",
+        Opcode::CSRRWI => "csrrwi",
+        Opcode::CSRRS => "csrrs",
+        Opcode::CSRRSI => "csrrsi",
+        Opcode::CSRRC => "csrrc",
+        Opcode::CSRRCI => "csrrci",
+        Opcode::URET => "uret",
+        Opcode::SRET => "sret",
+        Opcode::MRET => "mret",
+        Opcode::WFI => "wfi",
+        Opcode::ECALL => "ecall",
+        Opcode::EBREAK => "ebreak",
+        Opcode::FENCE => "fence",
+        Opcode::FENCEI => "fence.i",
+        Opcode::SFENCEVMA => "sfence.vma",
+    }
+}
+
+fn ordering(decode: &DecodeResult) -> &'static str {
+    match (decode.imm_i >> 6 & 1, decode.imm_i >> 5 & 1) {
+        (1, 1) => ".aqrl",
+        (1, 0) => ".aq",
+        (0, 1) => ".rl",
+        _ => "",
+    }
+}
+
+// FENCEのpred/succ(上位bitからi, o, r, w)
+fn fence_set(bits: u32) -> String {
+    let set: String = "iorw"
+        .chars()
+        .enumerate()
+        .filter(|&(index, _)| bits >> (3 - index) & 1 != 0)
+        .map(|(_, c)| c)
+        .collect();
+
+    if set.is_empty() {
+        "0".to_string()
+    } else {
+        set
+    }
+}
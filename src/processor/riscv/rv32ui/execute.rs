@@ -1,29 +1,6 @@
-use std::fmt::Display;
-
-use super::decode::Opcode;
 use super::decode::DecodeResult;
-use crate::processor::ProcessorError;
-use crate::processor::ProcessorErrorTrait;
-
-pub enum ExecuteErrorType {}
-
-pub struct ExecuteError {
-    error_type: ExecuteErrorType,
-}
-
-impl ExecuteError {
-    fn new(error_type: ExecuteErrorType) -> Box<Self> {
-        Box::new(ExecuteError { error_type })
-    }
-}
-
-impl ProcessorErrorTrait for ExecuteError {}
-
-impl Display for ExecuteError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        todo!()
-    }
-}
+use super::decode::Opcode;
+use super::trap::Exception;
 
 #[derive(Debug, Clone, Copy)]
 pub struct ExecuteResult {
@@ -35,10 +12,14 @@ pub struct ExecuteResult {
 pub struct Execute();
 
 impl Execute {
-    pub fn execute(&self, decode: DecodeResult, pc: u32) -> Result<ExecuteResult, ProcessorError> {
+    pub fn execute(&self, decode: DecodeResult, pc: u32) -> Result<ExecuteResult, Exception> {
         let alu_out: u32 = match decode.opcode {
-            Opcode::LW => (decode.rs1_data as i32 + decode.imm_i_sext) as u32,
-            Opcode::SW => (decode.rs1_data as i32 + decode.imm_s_sext) as u32,
+            Opcode::LB | Opcode::LH | Opcode::LW | Opcode::LBU | Opcode::LHU => {
+                (decode.rs1_data as i32).wrapping_add(decode.imm_i_sext) as u32
+            }
+            Opcode::SB | Opcode::SH | Opcode::SW => {
+                (decode.rs1_data as i32).wrapping_add(decode.imm_s_sext) as u32
+            }
 
             Opcode::ADD => decode.rs1_data.wrapping_add(decode.rs2_data),
             Opcode::ADDI => (decode.rs1_data as i32).wrapping_add(decode.imm_i_sext) as u32,
@@ -69,11 +50,50 @@ impl Execute {
             // Opcode::BGE => 0,
             // Opcode::BLTU => 0,
             // Opcode::BGEU => 0,
-            Opcode::JAL => pc + 4,
-            Opcode::JALR => pc + 4,
+            Opcode::JAL => pc.wrapping_add(decode.inst_len),
+            Opcode::JALR => pc.wrapping_add(decode.inst_len),
 
             Opcode::LUI => decode.imm_u_sext_shifted as u32,
             Opcode::AUIPC => ((pc as i32).wrapping_add(decode.imm_u_sext_shifted)) as u32,
+
+            // A拡張はrs1がそのままアドレスになる
+            Opcode::LRW
+            | Opcode::SCW
+            | Opcode::AMOSWAPW
+            | Opcode::AMOADDW
+            | Opcode::AMOXORW
+            | Opcode::AMOANDW
+            | Opcode::AMOORW
+            | Opcode::AMOMINW
+            | Opcode::AMOMAXW
+            | Opcode::AMOMINUW
+            | Opcode::AMOMAXUW => decode.rs1_data,
+
+            Opcode::MUL => decode.rs1_data.wrapping_mul(decode.rs2_data),
+            Opcode::MULH => {
+                ((decode.rs1_data as i32 as i64 * decode.rs2_data as i32 as i64) >> 32) as u32
+            }
+            Opcode::MULHSU => {
+                ((decode.rs1_data as i32 as i64 * decode.rs2_data as i64) >> 32) as u32
+            }
+            Opcode::MULHU => ((decode.rs1_data as u64 * decode.rs2_data as u64) >> 32) as u32,
+            // ゼロ除算とオーバーフローは例外を出さず仕様で決められた値を返す
+            Opcode::DIV => match decode.rs2_data {
+                0 => u32::MAX,
+                _ => (decode.rs1_data as i32).wrapping_div(decode.rs2_data as i32) as u32,
+            },
+            Opcode::DIVU => match decode.rs2_data {
+                0 => u32::MAX,
+                _ => decode.rs1_data / decode.rs2_data,
+            },
+            Opcode::REM => match decode.rs2_data {
+                0 => decode.rs1_data,
+                _ => (decode.rs1_data as i32).wrapping_rem(decode.rs2_data as i32) as u32,
+            },
+            Opcode::REMU => match decode.rs2_data {
+                0 => decode.rs1_data,
+                _ => decode.rs1_data % decode.rs2_data,
+            },
             // Opcode::CSRRW => 0,
             // Opcode::CSRRWI => 0,
             // Opcode::CSRRS => 0,
@@ -106,14 +126,14 @@ impl Execute {
             _ => None,
         };
 
-        print!("Execute: alu_out: {}", alu_out);
-        if let Some(br) = br_target {
-            print!(", br_target: 0x{:x}", br)
-        }
-        if let Some(jmp) = jmp_target {
-            print!(", jmp_target: 0x{:x}", jmp)
-        }
-        println!();
+        crate::trace!(
+            Exec,
+            Debug,
+            "Execute: alu_out: {}{}{}",
+            alu_out,
+            br_target.map_or(String::new(), |br| format!(", br_target: 0x{:x}", br)),
+            jmp_target.map_or(String::new(), |jmp| format!(", jmp_target: 0x{:x}", jmp))
+        );
 
         Ok(ExecuteResult {
             alu_out,
@@ -0,0 +1,60 @@
+// RV32Iに追加で有効にする拡張命令セット
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Extension {
+    I,
+    M,
+    A,
+    C,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Extensions {
+    pub m: bool,
+    pub a: bool,
+    pub c: bool,
+}
+
+impl Extensions {
+    pub fn rv32i() -> Self {
+        Self::default()
+    }
+
+    pub fn all() -> Self {
+        Self {
+            m: true,
+            a: true,
+            c: true,
+        }
+    }
+
+    // "rv32im" のようなISA文字列から有効にする拡張を決める
+    pub fn parse(isa: &str) -> Option<Self> {
+        let isa = isa.to_ascii_lowercase();
+        let mut letters = isa.strip_prefix("rv32")?.chars();
+
+        if letters.next()? != 'i' {
+            return None;
+        }
+
+        let mut extensions = Self::rv32i();
+        for letter in letters {
+            match letter {
+                'm' => extensions.m = true,
+                'a' => extensions.a = true,
+                'c' => extensions.c = true,
+                _ => return None,
+            }
+        }
+
+        Some(extensions)
+    }
+
+    pub fn contains(&self, extension: Extension) -> bool {
+        match extension {
+            Extension::I => true,
+            Extension::M => self.m,
+            Extension::A => self.a,
+            Extension::C => self.c,
+        }
+    }
+}
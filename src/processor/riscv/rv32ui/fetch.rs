@@ -1,37 +1,50 @@
-use std::fmt::Display;
+use super::compressed;
+use super::extension::{Extension, Extensions};
+use super::mmu::{AccessType, Mmu};
+use super::trap::Exception;
 
 use crate::bus::Bus;
-use crate::processor::ProcessorError;
-use crate::processor::ProcessorErrorTrait;
-
-pub enum FetchErrorType {}
-
-pub struct FetchError {
-    error_type: FetchErrorType,
-}
-
-impl FetchError {
-    fn new(error_type: FetchErrorType) -> Box<Self> {
-        Box::new(FetchError { error_type })
-    }
-}
-
-impl ProcessorErrorTrait for FetchError {}
-
-impl Display for FetchError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        todo!()
-    }
-}
 
 pub struct Fetch();
 
 impl Fetch {
-    pub fn fetch(&mut self, pc: u32, bus: &Bus) -> Result<u32, ProcessorError> {
-        println!("Fetch: 0x{:0>8x}", bus.read32(pc)?);
-
-        let physical_pc = pc;
-
-        bus.read32(physical_pc)
+    pub fn fetch(
+        &mut self,
+        pc: u32,
+        bus: &mut Bus,
+        extensions: &Extensions,
+        mmu: &mut Mmu,
+    ) -> Result<u32, Exception> {
+        // C拡張があれば2byte境界、無ければ4byte境界に揃っている必要がある
+        let alignment = if extensions.contains(Extension::C) {
+            2
+        } else {
+            4
+        };
+        if !pc.is_multiple_of(alignment) {
+            return Err(Exception::InstructionAddressMisaligned(pc));
+        }
+
+        let physical_pc = mmu.translate(pc, AccessType::Instruction, bus)?;
+
+        // 下位2bitが11以外なら16bit命令なので、後半の16bitは読まない
+        let low = bus
+            .read16(physical_pc)
+            .map_err(|_| Exception::InstructionAccessFault(pc))? as u32;
+        let inst = if compressed::is_compressed(low) {
+            low
+        } else {
+            // 後半の16bitは別のページにあるかもしれない
+            let high_pc = pc.wrapping_add(2);
+            let physical_high_pc = mmu.translate(high_pc, AccessType::Instruction, bus)?;
+            let high = bus
+                .read16(physical_high_pc)
+                .map_err(|_| Exception::InstructionAccessFault(high_pc))?;
+            (high as u32) << 16 | low
+        };
+
+        crate::trace!(Fetch, Debug, "Fetch: 0x{:0>8x} at 0x{:0>8x}", inst, pc);
+
+        Ok(inst)
     }
 }
@@ -0,0 +1,223 @@
+use super::cs_register::{
+    ControlAndStatusRegister, MSTATUS, MSTATUS_MPP_SHIFT, MSTATUS_MPRV, MSTATUS_MXR, MSTATUS_SUM,
+    SATP,
+};
+use super::trap::{Exception, Privilege};
+
+use crate::bus::Bus;
+
+const PAGE_SHIFT: u32 = 12;
+const PAGE_SIZE: u32 = 1 << PAGE_SHIFT;
+const PTE_SIZE: u32 = 4;
+const LEVELS: u32 = 2;
+
+const SATP_MODE_SV32: u32 = 1 << 31;
+const SATP_PPN_MASK: u32 = 0x003f_ffff;
+
+const PTE_V: u32 = 1 << 0;
+const PTE_R: u32 = 1 << 1;
+const PTE_W: u32 = 1 << 2;
+const PTE_X: u32 = 1 << 3;
+const PTE_U: u32 = 1 << 4;
+const PTE_A: u32 = 1 << 6;
+const PTE_D: u32 = 1 << 7;
+const PTE_PPN_SHIFT: u32 = 10;
+
+const TLB_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessType {
+    Instruction,
+    Load,
+    Store,
+}
+
+impl AccessType {
+    fn page_fault(&self, address: u32) -> Exception {
+        match self {
+            AccessType::Instruction => Exception::InstructionPageFault(address),
+            AccessType::Load => Exception::LoadPageFault(address),
+            AccessType::Store => Exception::StorePageFault(address),
+        }
+    }
+
+    fn access_fault(&self, address: u32) -> Exception {
+        match self {
+            AccessType::Instruction => Exception::InstructionAccessFault(address),
+            AccessType::Load => Exception::LoadAccessFault(address),
+            AccessType::Store => Exception::StoreAccessFault(address),
+        }
+    }
+}
+
+// 変換済みのページ。メガページも4KiB単位で登録する
+#[derive(Debug, Clone, Copy)]
+struct TlbEntry {
+    vpn: u32,
+    ppn: u32,
+    pte: u32,
+}
+
+// Sv32のアドレス変換を行う
+// satpとmstatusは命令ごとにupdateで取り込む
+#[derive(Debug, Clone)]
+pub struct Mmu {
+    satp: u32,
+    privilege: Privilege,
+    data_privilege: Privilege,
+    sum: bool,
+    mxr: bool,
+    tlb: [Option<TlbEntry>; TLB_SIZE],
+}
+
+impl Default for Mmu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mmu {
+    pub fn new() -> Self {
+        Self {
+            satp: 0,
+            privilege: Privilege::Machine,
+            data_privilege: Privilege::Machine,
+            sum: false,
+            mxr: false,
+            tlb: [None; TLB_SIZE],
+        }
+    }
+
+    pub fn update(&mut self, privilege: Privilege, csr: &ControlAndStatusRegister) {
+        let mstatus = csr.read(MSTATUS);
+
+        self.satp = csr.read(SATP);
+        self.privilege = privilege;
+        // MPRVが立っているとロード/ストアはMPPの特権レベルで行う
+        self.data_privilege = if mstatus & MSTATUS_MPRV != 0 {
+            Privilege::from_bits(mstatus >> MSTATUS_MPP_SHIFT)
+        } else {
+            privilege
+        };
+        self.sum = mstatus & MSTATUS_SUM != 0;
+        self.mxr = mstatus & MSTATUS_MXR != 0;
+    }
+
+    // SFENCE.VMA。アドレスが無ければ全て破棄する
+    pub fn flush(&mut self, address: Option<u32>) {
+        match address {
+            Some(address) => {
+                let vpn = address >> PAGE_SHIFT;
+                let index = vpn as usize % TLB_SIZE;
+                if self.tlb[index].is_some_and(|entry| entry.vpn == vpn) {
+                    self.tlb[index] = None;
+                }
+            }
+            None => self.tlb = [None; TLB_SIZE],
+        }
+    }
+
+    // 仮想アドレスを物理アドレスに変換する
+    pub fn translate(
+        &mut self,
+        address: u32,
+        access: AccessType,
+        bus: &mut Bus,
+    ) -> Result<u32, Exception> {
+        let privilege = match access {
+            AccessType::Instruction => self.privilege,
+            AccessType::Load | AccessType::Store => self.data_privilege,
+        };
+        if privilege == Privilege::Machine || self.satp & SATP_MODE_SV32 == 0 {
+            return Ok(address);
+        }
+
+        let vpn = address >> PAGE_SHIFT;
+        let index = vpn as usize % TLB_SIZE;
+        let entry = match self.tlb[index] {
+            Some(entry) if entry.vpn == vpn => entry,
+            _ => {
+                let entry = self.walk(address, access, bus)?;
+                self.tlb[index] = Some(entry);
+                entry
+            }
+        };
+
+        self.check_permission(entry.pte, address, access, privilege)?;
+
+        Ok(entry.ppn << PAGE_SHIFT | address & (PAGE_SIZE - 1))
+    }
+
+    // 2段のページテーブルを辿ってリーフのPTEを探す
+    fn walk(&self, address: u32, access: AccessType, bus: &mut Bus) -> Result<TlbEntry, Exception> {
+        let vpn = [address >> 12 & 0x3ff, address >> 22 & 0x3ff];
+        let mut table = (self.satp & SATP_PPN_MASK) << PAGE_SHIFT;
+
+        for level in (0..LEVELS).rev() {
+            let pte_address = table.wrapping_add(vpn[level as usize] * PTE_SIZE);
+            let pte = bus
+                .read32(pte_address)
+                .map_err(|_| access.access_fault(address))?;
+
+            if pte & PTE_V == 0 || (pte & PTE_R == 0 && pte & PTE_W != 0) {
+                return Err(access.page_fault(address));
+            }
+
+            let ppn = pte >> PTE_PPN_SHIFT;
+            if pte & (PTE_R | PTE_X) == 0 {
+                table = ppn << PAGE_SHIFT;
+                continue;
+            }
+
+            // メガページはPPN[0]が0でなければならない
+            if level == 1 {
+                if ppn & 0x3ff != 0 {
+                    return Err(access.page_fault(address));
+                }
+                return Ok(TlbEntry {
+                    vpn: address >> PAGE_SHIFT,
+                    ppn: ppn | vpn[0],
+                    pte,
+                });
+            }
+
+            return Ok(TlbEntry {
+                vpn: address >> PAGE_SHIFT,
+                ppn,
+                pte,
+            });
+        }
+
+        Err(access.page_fault(address))
+    }
+
+    // A/Dビットはハードウェアで更新せず、立っていなければページフォールトにする
+    fn check_permission(
+        &self,
+        pte: u32,
+        address: u32,
+        access: AccessType,
+        privilege: Privilege,
+    ) -> Result<(), Exception> {
+        let user_page = pte & PTE_U != 0;
+        let privilege_ok = match privilege {
+            Privilege::User => user_page,
+            Privilege::Supervisor => !user_page || (self.sum && access != AccessType::Instruction),
+            Privilege::Machine => true,
+        };
+
+        let access_ok = match access {
+            AccessType::Instruction => pte & PTE_X != 0,
+            AccessType::Load => pte & PTE_R != 0 || (self.mxr && pte & PTE_X != 0),
+            AccessType::Store => pte & PTE_W != 0,
+        };
+
+        let accessed = pte & PTE_A != 0 && (access != AccessType::Store || pte & PTE_D != 0);
+
+        if privilege_ok && access_ok && accessed {
+            Ok(())
+        } else {
+            Err(access.page_fault(address))
+        }
+    }
+}
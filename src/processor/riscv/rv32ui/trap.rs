@@ -0,0 +1,287 @@
+use std::fmt::Display;
+
+use crate::processor::ProcessorErrorTrait;
+
+use super::cs_register::{
+    ControlAndStatusRegister, MCAUSE, MEDELEG, MEPC, MIDELEG, MSTATUS, MSTATUS_MIE, MSTATUS_MPIE,
+    MSTATUS_MPP, MSTATUS_MPP_SHIFT, MSTATUS_MPRV, MSTATUS_SIE, MSTATUS_SPIE, MSTATUS_SPP,
+    MSTATUS_SPP_SHIFT, MTVAL, SCAUSE, SEPC, STVAL,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Privilege {
+    User = 0,
+    Supervisor = 1,
+    Machine = 3,
+}
+
+impl Privilege {
+    pub fn from_bits(bits: u32) -> Self {
+        match bits & 0b11 {
+            0 => Privilege::User,
+            1 => Privilege::Supervisor,
+            _ => Privilege::Machine,
+        }
+    }
+}
+
+// 同期例外。引数はmtvalに書き込む値
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exception {
+    InstructionAddressMisaligned(u32),
+    InstructionAccessFault(u32),
+    IllegalInstruction(u32),
+    Breakpoint(u32),
+    LoadAddressMisaligned(u32),
+    LoadAccessFault(u32),
+    StoreAddressMisaligned(u32),
+    StoreAccessFault(u32),
+    EnvironmentCall(Privilege),
+    InstructionPageFault(u32),
+    LoadPageFault(u32),
+    StorePageFault(u32),
+}
+
+impl Exception {
+    pub fn cause(&self) -> u32 {
+        match self {
+            Exception::InstructionAddressMisaligned(_) => 0,
+            Exception::InstructionAccessFault(_) => 1,
+            Exception::IllegalInstruction(_) => 2,
+            Exception::Breakpoint(_) => 3,
+            Exception::LoadAddressMisaligned(_) => 4,
+            Exception::LoadAccessFault(_) => 5,
+            Exception::StoreAddressMisaligned(_) => 6,
+            Exception::StoreAccessFault(_) => 7,
+            Exception::EnvironmentCall(privilege) => 8 + *privilege as u32,
+            Exception::InstructionPageFault(_) => 12,
+            Exception::LoadPageFault(_) => 13,
+            Exception::StorePageFault(_) => 15,
+        }
+    }
+
+    pub fn tval(&self) -> u32 {
+        match self {
+            Exception::InstructionAddressMisaligned(tval)
+            | Exception::InstructionAccessFault(tval)
+            | Exception::IllegalInstruction(tval)
+            | Exception::Breakpoint(tval)
+            | Exception::LoadAddressMisaligned(tval)
+            | Exception::LoadAccessFault(tval)
+            | Exception::StoreAddressMisaligned(tval)
+            | Exception::StoreAccessFault(tval)
+            | Exception::InstructionPageFault(tval)
+            | Exception::LoadPageFault(tval)
+            | Exception::StorePageFault(tval) => *tval,
+            Exception::EnvironmentCall(_) => 0,
+        }
+    }
+}
+
+// 割り込み。値はmcauseの例外コード
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    SupervisorSoftware = 1,
+    MachineSoftware = 3,
+    SupervisorTimer = 5,
+    MachineTimer = 7,
+    SupervisorExternal = 9,
+    MachineExternal = 11,
+}
+
+impl Interrupt {
+    // 同時に発生したときに優先する順
+    pub const PRIORITY: [Interrupt; 6] = [
+        Interrupt::MachineExternal,
+        Interrupt::MachineSoftware,
+        Interrupt::MachineTimer,
+        Interrupt::SupervisorExternal,
+        Interrupt::SupervisorSoftware,
+        Interrupt::SupervisorTimer,
+    ];
+
+    pub fn cause(&self) -> u32 {
+        INTERRUPT_BIT | *self as u32
+    }
+
+    // mip/mieでのビット
+    pub fn bit(&self) -> u32 {
+        1 << *self as u32
+    }
+}
+
+pub enum TrapErrorType {
+    // トラップハンドラが設定されていない状態で例外が発生した
+    UnhandledException(Exception, u32),
+}
+
+pub struct TrapError {
+    error_type: TrapErrorType,
+}
+
+impl TrapError {
+    pub fn new(error_type: TrapErrorType) -> Box<Self> {
+        Box::new(TrapError { error_type })
+    }
+}
+
+impl ProcessorErrorTrait for TrapError {}
+
+impl Display for TrapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            TrapErrorType::UnhandledException(exception, pc) => write!(
+                f,
+                "Unhandled exception {:?} (mtval 0x{:0>8x}) at 0x{:0>8x}",
+                exception,
+                exception.tval(),
+                pc
+            ),
+        }
+    }
+}
+
+const INTERRUPT_BIT: u32 = 1 << 31;
+
+const MTVEC_MODE_MASK: u32 = 0b11;
+const MTVEC_MODE_VECTORED: u32 = 1;
+
+pub struct Trap();
+
+impl Trap {
+    // トラップに入る。委譲されていればSモード、そうでなければMモードのハンドラに移り、
+    // ジャンプ先と新しい特権レベルを返す
+    // 移る先のトラップハンドラが設定されていなければ、CSRを変更せずにNoneを返す
+    pub fn enter(
+        &self,
+        cause: u32,
+        tval: u32,
+        pc: u32,
+        privilege: Privilege,
+        csr: &mut ControlAndStatusRegister,
+    ) -> Option<(u32, Privilege)> {
+        if Self::is_delegated(cause, privilege, csr) {
+            let tvec = csr.stvec()?;
+            self.enter_supervisor(cause, tval, pc, privilege, csr);
+            Some((Self::vector(tvec, cause), Privilege::Supervisor))
+        } else {
+            let tvec = csr.mtvec()?;
+            self.enter_machine(cause, tval, pc, privilege, csr);
+            Some((Self::vector(tvec, cause), Privilege::Machine))
+        }
+    }
+
+    // このトラップをSモードで処理するか
+    pub fn is_delegated(cause: u32, privilege: Privilege, csr: &ControlAndStatusRegister) -> bool {
+        let delegation = if cause & INTERRUPT_BIT != 0 {
+            csr.read(MIDELEG)
+        } else {
+            csr.read(MEDELEG)
+        };
+        // Mモードで発生したトラップは委譲されない
+        privilege <= Privilege::Supervisor && delegation >> (cause & !INTERRUPT_BIT) & 1 != 0
+    }
+
+    fn enter_machine(
+        &self,
+        cause: u32,
+        tval: u32,
+        pc: u32,
+        privilege: Privilege,
+        csr: &mut ControlAndStatusRegister,
+    ) {
+        csr.write(MEPC, pc);
+        csr.write(MCAUSE, cause);
+        csr.write(MTVAL, tval);
+
+        let mut mstatus = csr.read(MSTATUS);
+        // MIEをMPIEに退避して割り込みを禁止し、元の特権レベルをMPPに保存する
+        if mstatus & MSTATUS_MIE != 0 {
+            mstatus |= MSTATUS_MPIE;
+        } else {
+            mstatus &= !MSTATUS_MPIE;
+        }
+        mstatus &= !MSTATUS_MIE;
+        mstatus = (mstatus & !MSTATUS_MPP) | (privilege as u32) << MSTATUS_MPP_SHIFT;
+        csr.write(MSTATUS, mstatus);
+    }
+
+    fn enter_supervisor(
+        &self,
+        cause: u32,
+        tval: u32,
+        pc: u32,
+        privilege: Privilege,
+        csr: &mut ControlAndStatusRegister,
+    ) {
+        csr.write(SEPC, pc);
+        csr.write(SCAUSE, cause);
+        csr.write(STVAL, tval);
+
+        let mut mstatus = csr.read(MSTATUS);
+        // SIEをSPIEに退避して割り込みを禁止し、元の特権レベル(UかS)をSPPに保存する
+        if mstatus & MSTATUS_SIE != 0 {
+            mstatus |= MSTATUS_SPIE;
+        } else {
+            mstatus &= !MSTATUS_SPIE;
+        }
+        mstatus &= !MSTATUS_SIE;
+        mstatus = (mstatus & !MSTATUS_SPP) | (privilege as u32 & 1) << MSTATUS_SPP_SHIFT;
+        csr.write(MSTATUS, mstatus);
+    }
+
+    // MRET。mstatusを元に戻し、戻り先と新しい特権レベルを返す
+    pub fn mret(&self, csr: &mut ControlAndStatusRegister) -> (u32, Privilege) {
+        let mut mstatus = csr.read(MSTATUS);
+        let privilege = Privilege::from_bits(mstatus >> MSTATUS_MPP_SHIFT);
+
+        if mstatus & MSTATUS_MPIE != 0 {
+            mstatus |= MSTATUS_MIE;
+        } else {
+            mstatus &= !MSTATUS_MIE;
+        }
+        mstatus |= MSTATUS_MPIE;
+        // MPPはサポートしている最も低い特権レベルにする
+        mstatus = (mstatus & !MSTATUS_MPP) | (Privilege::User as u32) << MSTATUS_MPP_SHIFT;
+        // Mモード以外に戻るときはMPRVをクリアする
+        if privilege != Privilege::Machine {
+            mstatus &= !MSTATUS_MPRV;
+        }
+        csr.write(MSTATUS, mstatus);
+
+        (csr.read(MEPC), privilege)
+    }
+
+    // SRET。sstatusを元に戻し、戻り先と新しい特権レベルを返す
+    pub fn sret(&self, csr: &mut ControlAndStatusRegister) -> (u32, Privilege) {
+        let mut mstatus = csr.read(MSTATUS);
+        let privilege = if mstatus & MSTATUS_SPP != 0 {
+            Privilege::Supervisor
+        } else {
+            Privilege::User
+        };
+
+        if mstatus & MSTATUS_SPIE != 0 {
+            mstatus |= MSTATUS_SIE;
+        } else {
+            mstatus &= !MSTATUS_SIE;
+        }
+        mstatus |= MSTATUS_SPIE;
+        mstatus &= !MSTATUS_SPP;
+        mstatus &= !MSTATUS_MPRV;
+        csr.write(MSTATUS, mstatus);
+
+        (csr.read(SEPC), privilege)
+    }
+
+    // Vectoredモードでは割り込みのみBASE + 4 * causeに飛ぶ
+    fn vector(tvec: u32, cause: u32) -> u32 {
+        let base = tvec & !MTVEC_MODE_MASK;
+
+        if tvec & MTVEC_MODE_MASK == MTVEC_MODE_VECTORED && cause & INTERRUPT_BIT != 0 {
+            base.wrapping_add(4 * (cause & !INTERRUPT_BIT))
+        } else {
+            base
+        }
+    }
+}
@@ -1,37 +1,28 @@
-use std::fmt::Display;
-
 use super::cs_register::ControlAndStatusRegister;
 use super::decode::DecodeResult;
 use super::decode::Opcode;
 use super::execute::ExecuteResult;
+use super::mmu::{AccessType, Mmu};
+use super::trap::Exception;
 use super::x_register::XRegisters;
 
 use crate::bus::Bus;
-use crate::processor::ProcessorError;
-use crate::processor::ProcessorErrorTrait;
-
-pub enum WritebackErrorType {}
+use crate::config::MisalignedAccess;
 
-pub struct WritebackError {
-    error_type: WritebackErrorType,
+// 実際に行ったメモリアクセス。コミットログの出力に使う
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WritebackResult {
+    // 読んだ仮想アドレスとバイト数
+    pub load: Option<(u32, u32)>,
+    // 書き込んだ仮想アドレス、値、バイト数
+    pub store: Option<(u32, u32, u32)>,
 }
 
-impl WritebackError {
-    fn new(error_type: WritebackErrorType) -> Box<Self> {
-        Box::new(WritebackError { error_type })
-    }
+pub struct Writeback {
+    // 境界に揃っていないロード/ストアの扱い
+    pub misaligned_access: MisalignedAccess,
 }
 
-impl ProcessorErrorTrait for WritebackError {}
-
-impl Display for WritebackError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        todo!()
-    }
-}
-
-pub struct Writeback();
-
 impl Writeback {
     pub fn writeback(
         &self,
@@ -40,13 +31,22 @@ impl Writeback {
         xregs: &mut XRegisters,
         csr: &mut ControlAndStatusRegister,
         bus: &mut Bus,
-    ) -> Result<(), ProcessorError> {
-        if let Ok(rd_data) = bus.read32(execute.alu_out) {
-            println!("Writeback: rd(wb_data) 0x{:0>8x}({})", rd_data, rd_data);
-        };
+        mmu: &mut Mmu,
+    ) -> Result<WritebackResult, Exception> {
+        let misaligned = self.misaligned_access;
+        let mut result = WritebackResult::default();
+        // デバイスのレジスタは読むだけで状態が変わるので、ここではBusを読まない
+        crate::trace!(
+            Exec,
+            Debug,
+            "Writeback: alu_out 0x{:0>8x}({})",
+            execute.alu_out,
+            execute.alu_out
+        );
         let crs_data = csr.read(decode.csr);
 
         match decode.opcode {
+            _ if !decode.writes_csr() => (),
             Opcode::CSRRW => csr.write(decode.csr, decode.rs1_data),
             Opcode::CSRRWI => csr.write(decode.csr, decode.imm_z),
             Opcode::CSRRS => csr.write(decode.csr, csr.read(decode.csr) | decode.rs1_data),
@@ -55,10 +55,113 @@ impl Writeback {
             Opcode::CSRRCI => csr.write(decode.csr, csr.read(decode.csr) & (!decode.imm_z)),
             _ => (),
         }
+        if decode.writes_csr() {
+            crate::trace!(
+                Csr,
+                Debug,
+                "CSR: 0x{:0>3x}: 0x{:0>8x} -> 0x{:0>8x}",
+                decode.csr,
+                crs_data,
+                csr.read(decode.csr)
+            );
+        }
+
+        // アクセスできないアドレスはアクセスフォールトとしてゲストに通知する
+        // mtvalには変換前の仮想アドレスを入れる
+        let address = execute.alu_out;
+        let load_fault = |_| Exception::LoadAccessFault(address);
+        let store_fault = |_| Exception::StoreAccessFault(address);
 
         match decode.opcode {
-            Opcode::LW => xregs.write(decode.rd, bus.read32(execute.alu_out)?),
-            Opcode::SW => bus.write32(execute.alu_out, decode.rs2_data)?,
+            Opcode::LB => xregs.write(
+                decode.rd,
+                load(address, 1, bus, mmu, misaligned, &mut result)? as i8 as u32,
+            ),
+            Opcode::LH => xregs.write(
+                decode.rd,
+                load(address, 2, bus, mmu, misaligned, &mut result)? as i16 as u32,
+            ),
+            Opcode::LW => xregs.write(
+                decode.rd,
+                load(address, 4, bus, mmu, misaligned, &mut result)?,
+            ),
+            Opcode::LBU => xregs.write(
+                decode.rd,
+                load(address, 1, bus, mmu, misaligned, &mut result)?,
+            ),
+            Opcode::LHU => xregs.write(
+                decode.rd,
+                load(address, 2, bus, mmu, misaligned, &mut result)?,
+            ),
+
+            Opcode::SB => store(
+                address,
+                1,
+                decode.rs2_data,
+                bus,
+                mmu,
+                misaligned,
+                &mut result,
+            )?,
+            Opcode::SH => store(
+                address,
+                2,
+                decode.rs2_data,
+                bus,
+                mmu,
+                misaligned,
+                &mut result,
+            )?,
+            Opcode::SW => store(
+                address,
+                4,
+                decode.rs2_data,
+                bus,
+                mmu,
+                misaligned,
+                &mut result,
+            )?,
+
+            Opcode::LRW => {
+                let physical = atomic_address(address, AccessType::Load, bus, mmu)?;
+                xregs.write(decode.rd, bus.load_reserved(physical).map_err(load_fault)?);
+                result.load = Some((address, 4));
+            }
+            // 成功なら0、失敗なら1をrdに書き込む
+            Opcode::SCW => {
+                let physical = atomic_address(address, AccessType::Store, bus, mmu)?;
+                let success = bus
+                    .store_conditional(physical, decode.rs2_data)
+                    .map_err(store_fault)?;
+                xregs.write(decode.rd, !success as u32);
+                if success {
+                    result.store = Some((address, decode.rs2_data, 4));
+                }
+            }
+            Opcode::AMOSWAPW
+            | Opcode::AMOADDW
+            | Opcode::AMOXORW
+            | Opcode::AMOANDW
+            | Opcode::AMOORW
+            | Opcode::AMOMINW
+            | Opcode::AMOMAXW
+            | Opcode::AMOMINUW
+            | Opcode::AMOMAXUW => {
+                // AMOのフォールトはストアとして扱う
+                let physical = atomic_address(address, AccessType::Store, bus, mmu)?;
+                let value = bus
+                    .amo32(physical, |memory| {
+                        amo_operation(decode.opcode, memory, decode.rs2_data)
+                    })
+                    .map_err(store_fault)?;
+                xregs.write(decode.rd, value);
+                result.load = Some((address, 4));
+                result.store = Some((
+                    address,
+                    amo_operation(decode.opcode, value, decode.rs2_data),
+                    4,
+                ));
+            }
 
             Opcode::BEQ => (),
             Opcode::BNE => (),
@@ -74,15 +177,166 @@ impl Writeback {
             Opcode::CSRRC => xregs.write(decode.rd, crs_data),
             Opcode::CSRRCI => xregs.write(decode.rd, crs_data),
 
-            Opcode::ECALL => csr.write(0x342, 11),
-
-            Opcode::MRET => (), // todo
+            // トラップ処理はRiscVUIProcessorで行う
+            Opcode::ECALL => (),
+            Opcode::EBREAK => (),
+            Opcode::MRET => (),
+            Opcode::SRET => (),
+            // 割り込みを待たずにそのまま次の命令に進む
+            Opcode::WFI => (),
 
             Opcode::FENCE => (), // todo
+            // 命令キャッシュを持たないので何もしない
+            Opcode::FENCEI => (),
+            // rs1が0なら全てのエントリを破棄する
+            // rs1がx0のときだけ全て破棄する。値が0でもx0以外ならそのアドレスだけ
+            Opcode::SFENCEVMA => mmu.flush((decode.rs1 != 0).then_some(decode.rs1_data)),
 
             _ => xregs.write(decode.rd, execute.alu_out),
         }
 
-        Ok(())
+        Ok(result)
+    }
+}
+
+// widthバイトを読み、下位ビットに詰めて返す
+// 境界に揃っていなければmisalignedに従って例外にするか1byteずつ読む
+fn load(
+    address: u32,
+    width: u32,
+    bus: &mut Bus,
+    mmu: &mut Mmu,
+    misaligned: MisalignedAccess,
+    result: &mut WritebackResult,
+) -> Result<u32, Exception> {
+    if address.is_multiple_of(width) {
+        let physical = mmu.translate(address, AccessType::Load, bus)?;
+        let value = match width {
+            1 => bus.read8(physical).map(u32::from),
+            2 => bus.read16(physical).map(u32::from),
+            _ => bus.read32(physical),
+        };
+        let value = value.map_err(|_| Exception::LoadAccessFault(address))?;
+        crate::trace!(
+            Mem,
+            Debug,
+            "Mem: load 0x{:0>8x} -> 0x{:0>8x}",
+            address,
+            value
+        );
+        result.load = Some((address, width));
+        return Ok(value);
+    }
+
+    if misaligned == MisalignedAccess::Trap {
+        return Err(Exception::LoadAddressMisaligned(address));
+    }
+
+    // ページやデバイスをまたぐかもしれないので1byteずつ変換する
+    let mut value = 0;
+    for index in 0..width {
+        let byte_address = address.wrapping_add(index);
+        let physical = mmu.translate(byte_address, AccessType::Load, bus)?;
+        let byte = bus
+            .read8(physical)
+            .map_err(|_| Exception::LoadAccessFault(byte_address))?;
+        value |= (byte as u32) << (index * 8);
+    }
+    crate::trace!(
+        Mem,
+        Debug,
+        "Mem: load 0x{:0>8x} -> 0x{:0>8x} (split)",
+        address,
+        value
+    );
+    result.load = Some((address, width));
+
+    Ok(value)
+}
+
+// valueの下位widthバイトを書き込む
+fn store(
+    address: u32,
+    width: u32,
+    value: u32,
+    bus: &mut Bus,
+    mmu: &mut Mmu,
+    misaligned: MisalignedAccess,
+    result: &mut WritebackResult,
+) -> Result<(), Exception> {
+    crate::trace!(
+        Mem,
+        Debug,
+        "Mem: store 0x{:0>8x} <- 0x{:0>8x}",
+        address,
+        value
+    );
+    let mask = u32::MAX >> (32 - width * 8);
+
+    if address.is_multiple_of(width) {
+        let physical = mmu.translate(address, AccessType::Store, bus)?;
+        let written = match width {
+            1 => bus.write8(physical, value as u8),
+            2 => bus.write16(physical, value as u16),
+            _ => bus.write32(physical, value),
+        };
+        written.map_err(|_| Exception::StoreAccessFault(address))?;
+        result.store = Some((address, value & mask, width));
+        return Ok(());
+    }
+
+    if misaligned == MisalignedAccess::Trap {
+        return Err(Exception::StoreAddressMisaligned(address));
+    }
+
+    // 途中で失敗して一部だけ書き込まれないよう、先に全て変換してメモリであることを確かめておく
+    // デバイスは1byteずつの書き込みを受け付けるとは限らないので、分割したストアはメモリにしか行えない
+    let mut physical = [0; 4];
+    for index in 0..width {
+        let byte_address = address.wrapping_add(index);
+        let byte_physical = mmu.translate(byte_address, AccessType::Store, bus)?;
+        if !bus.is_memory(byte_physical) {
+            return Err(Exception::StoreAccessFault(byte_address));
+        }
+        physical[index as usize] = byte_physical;
+    }
+    for index in 0..width {
+        bus.write8(physical[index as usize], (value >> (index * 8)) as u8)
+            .map_err(|_| Exception::StoreAccessFault(address.wrapping_add(index)))?;
+    }
+    result.store = Some((address, value & mask, width));
+
+    Ok(())
+}
+
+// LR/SC/AMOは分割できないので、境界に揃っていなければ常に例外にする
+fn atomic_address(
+    address: u32,
+    access: AccessType,
+    bus: &mut Bus,
+    mmu: &mut Mmu,
+) -> Result<u32, Exception> {
+    if !address.is_multiple_of(4) {
+        return Err(match access {
+            AccessType::Load => Exception::LoadAddressMisaligned(address),
+            _ => Exception::StoreAddressMisaligned(address),
+        });
+    }
+
+    mmu.translate(address, access, bus)
+}
+
+fn amo_operation(opcode: Opcode, memory: u32, rs2_data: u32) -> u32 {
+    match opcode {
+        Opcode::AMOSWAPW => rs2_data,
+        Opcode::AMOADDW => memory.wrapping_add(rs2_data),
+        Opcode::AMOXORW => memory ^ rs2_data,
+        Opcode::AMOANDW => memory & rs2_data,
+        Opcode::AMOORW => memory | rs2_data,
+        Opcode::AMOMINW => (memory as i32).min(rs2_data as i32) as u32,
+        Opcode::AMOMAXW => (memory as i32).max(rs2_data as i32) as u32,
+        Opcode::AMOMINUW => memory.min(rs2_data),
+        Opcode::AMOMAXUW => memory.max(rs2_data),
+        _ => memory,
     }
 }
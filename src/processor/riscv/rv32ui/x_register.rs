@@ -1,6 +1,6 @@
 use std::{fmt::Display, ops::Add};
 
-use crate::{bus::DRAM_BASE, dram::DRAM_SIZE};
+use crate::{bus::DRAM_BASE, config::DEFAULT_DRAM_SIZE};
 
 const REGISTERS_COUNT: usize = 32;
 
@@ -9,13 +9,20 @@ pub struct XRegisters {
     xregs: [u32; REGISTERS_COUNT],
 }
 
+impl Default for XRegisters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl XRegisters {
     // todo
     pub fn new() -> Self {
         let mut xregs = [0u32; REGISTERS_COUNT];
 
         // スタックポインターはデフォルトでメモリのスタートアドレス + 最大メモリサイズを入れる
-        xregs[2] = DRAM_BASE + DRAM_SIZE;
+        // メモリ構成が違う場合はProcessor::resetで設定し直す
+        xregs[2] = DRAM_BASE + DEFAULT_DRAM_SIZE;
 
         Self { xregs }
     }
@@ -38,14 +45,22 @@ const XREGS_CALL: [&str; 32] = [
     "t5", "t6",
 ];
 
+// レジスタのABI名。x8は"s0"とする
+pub fn abi_name(index: u32) -> &'static str {
+    match XREGS_CALL[index as usize].split_once('/') {
+        Some((name, _)) => name,
+        None => XREGS_CALL[index as usize],
+    }
+}
+
 impl Display for XRegisters {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut res = String::new();
-        for i in 0..REGISTERS_COUNT {
+        for (i, name) in XREGS_CALL.iter().enumerate() {
             let s = format!(
                 "\x1b[38;5;4m{:0>2}-{}:\x1b[m 0x{:x}, ",
                 i,
-                XREGS_CALL[i],
+                name,
                 self.read(i as u32)
             );
             res = res.add(&s);
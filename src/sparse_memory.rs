@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use crate::{
+    device::Device,
+    dram::{DramError, DramErrorType},
+    processor::ProcessorError,
+};
+
+pub const PAGE_SHIFT: u32 = 12;
+pub const PAGE_SIZE: usize = 1 << PAGE_SHIFT; // 4KiB
+
+// 4KiBのページを最初に書き込まれたときに確保するメモリ
+// 書き込まれていないページは0として読むので、大きな領域でも最初は何も確保しない
+#[derive(Debug, Clone)]
+pub struct SparseMemory {
+    size: u32,
+    pages: BTreeMap<u32, Box<[u8; PAGE_SIZE]>>,
+}
+
+impl SparseMemory {
+    pub fn new(size: u32) -> Self {
+        Self {
+            size,
+            pages: BTreeMap::new(),
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    fn check(&self, address: u32, length: usize) -> Result<(), ProcessorError> {
+        if address as u64 + length as u64 <= self.size as u64 {
+            Ok(())
+        } else {
+            Err(DramError::new(DramErrorType::AddressOutOfBounds))
+        }
+    }
+
+    // 書き込まれたページの先頭を、この領域の先頭からのオフセットで昇順に返す
+    // ゲストの物理アドレスが必要なときはBus::touched_pagesを使う
+    pub fn touched_page_offsets(&self) -> impl Iterator<Item = u32> + '_ {
+        self.pages.keys().map(|page| page << PAGE_SHIFT)
+    }
+
+    // addressを含むページの内容。書き込まれていなければNone
+    pub fn page(&self, address: u32) -> Option<&[u8; PAGE_SIZE]> {
+        self.pages.get(&(address >> PAGE_SHIFT)).map(|page| &**page)
+    }
+
+    pub fn read8(&self, address: u32) -> Result<u8, ProcessorError> {
+        self.check(address, 1)?;
+        Ok(self.read_byte(address))
+    }
+
+    pub fn read_byte(&self, address: u32) -> u8 {
+        self.page(address)
+            .map_or(0, |page| page[address as usize % PAGE_SIZE])
+    }
+
+    pub fn write_byte(&mut self, address: u32, value: u8) {
+        let page = self
+            .pages
+            .entry(address >> PAGE_SHIFT)
+            .or_insert_with(|| Box::new([0; PAGE_SIZE]));
+        page[address as usize % PAGE_SIZE] = value;
+    }
+}
+
+impl Device for SparseMemory {
+    fn read8(&mut self, offset: u32) -> Result<u8, ProcessorError> {
+        SparseMemory::read8(self, offset)
+    }
+
+    fn read16(&mut self, offset: u32) -> Result<u16, ProcessorError> {
+        self.check(offset, 2)?;
+        Ok((self.read_byte(offset + 1) as u16) << 8 | self.read_byte(offset) as u16)
+    }
+
+    fn read32(&mut self, offset: u32) -> Result<u32, ProcessorError> {
+        self.check(offset, 4)?;
+        Ok((self.read_byte(offset + 3) as u32) << 24
+            | (self.read_byte(offset + 2) as u32) << 16
+            | (self.read_byte(offset + 1) as u32) << 8
+            | self.read_byte(offset) as u32)
+    }
+
+    fn write8(&mut self, offset: u32, value: u8) -> Result<(), ProcessorError> {
+        self.check(offset, 1)?;
+        self.write_byte(offset, value);
+        Ok(())
+    }
+
+    fn write16(&mut self, offset: u32, value: u16) -> Result<(), ProcessorError> {
+        self.check(offset, 2)?;
+        self.write_byte(offset + 1, (value >> 8) as u8);
+        self.write_byte(offset, value as u8);
+        Ok(())
+    }
+
+    fn write32(&mut self, offset: u32, value: u32) -> Result<(), ProcessorError> {
+        self.check(offset, 4)?;
+        self.write_byte(offset + 3, (value >> 24) as u8);
+        self.write_byte(offset + 2, (value >> 16) as u8);
+        self.write_byte(offset + 1, (value >> 8) as u8);
+        self.write_byte(offset, value as u8);
+        Ok(())
+    }
+
+    // ゼロは確保せずに済むので、確保されていないページには書かない
+    fn write_bytes(&mut self, offset: u32, data: &[u8]) -> Result<(), ProcessorError> {
+        self.check(offset, data.len())?;
+
+        for (count, &value) in data.iter().enumerate() {
+            let address = offset + count as u32;
+            if value != 0 || self.page(address).is_some() {
+                self.write_byte(address, value);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// 書き込まれたページだけを表示する
+impl Display for SparseMemory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (&page, data) in &self.pages {
+            for (line, bytes) in data.chunks(16).enumerate() {
+                write!(f, "{:0>8x}:", (page << PAGE_SHIFT) + line as u32 * 16)?;
+                for byte in bytes {
+                    write!(f, " {:0>2x}", byte)?;
+                }
+                writeln!(f)?;
+            }
+        }
+
+        Ok(())
+    }
+}
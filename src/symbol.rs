@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+
+use crate::elf::{ElfSymbol, STB_GLOBAL, STT_FUNC, STT_NOTYPE, STT_OBJECT};
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u32,
+    pub size: u32,
+    pub global: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    by_address: BTreeMap<u32, Symbol>,
+    by_name: BTreeMap<String, u32>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_elf_symbols(elf_symbols: &[ElfSymbol]) -> Self {
+        let mut table = Self::new();
+
+        for symbol in elf_symbols {
+            // 未定義シンボル、セクション/ファイルシンボル、$xなどのマッピングシンボルは除外する
+            let is_label = matches!(symbol.symbol_type(), STT_NOTYPE | STT_OBJECT | STT_FUNC);
+            if !is_label
+                || symbol.shndx == 0
+                || symbol.name.is_empty()
+                || symbol.name.starts_with('$')
+            {
+                continue;
+            }
+
+            table.insert(Symbol {
+                name: symbol.name.clone(),
+                address: symbol.value,
+                size: symbol.size,
+                global: symbol.binding() == STB_GLOBAL,
+            });
+        }
+
+        table
+    }
+
+    pub fn insert(&mut self, symbol: Symbol) {
+        self.by_name.insert(symbol.name.clone(), symbol.address);
+
+        // 同じアドレスに複数のシンボルがある場合はグローバルなものを優先する
+        match self.by_address.get(&symbol.address) {
+            Some(existing) if existing.global || !symbol.global => (),
+            _ => {
+                self.by_address.insert(symbol.address, symbol);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_address.is_empty()
+    }
+
+    pub fn address_of(&self, name: &str) -> Option<u32> {
+        self.by_name.get(name).copied()
+    }
+
+    // addressを含むシンボルとシンボル先頭からのオフセットを返す
+    pub fn lookup(&self, address: u32) -> Option<(&Symbol, u32)> {
+        let (_, symbol) = self.by_address.range(..=address).next_back()?;
+        let offset = address - symbol.address;
+
+        if symbol.size != 0 && offset >= symbol.size {
+            None
+        } else {
+            Some((symbol, offset))
+        }
+    }
+
+    // "0x8000019c <test_2+0x10>" の形式で表示する
+    pub fn format_address(&self, address: u32) -> String {
+        match self.lookup(address) {
+            Some((symbol, 0)) => format!("0x{:0>8x} <{}>", address, symbol.name),
+            Some((symbol, offset)) => {
+                format!("0x{:0>8x} <{}+0x{:x}>", address, symbol.name, offset)
+            }
+            None => format!("0x{:0>8x}", address),
+        }
+    }
+}
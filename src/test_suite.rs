@@ -0,0 +1,115 @@
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::bus::Bus;
+use crate::computer::{Computer, RunResult};
+use crate::processor::riscv::rv32ui::extension::Extensions;
+use crate::processor::riscv::rv32ui::RiscVUIProcessor;
+
+pub const DEFAULT_STEP_LIMIT: u64 = 1_000_000;
+
+const TEST_PREFIXES: [&str; 6] = [
+    "rv32ui-p-",
+    "rv32ui-v-",
+    "rv32um-p-",
+    "rv32um-v-",
+    "rv32ua-p-",
+    "rv32ua-v-",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestOutcome {
+    Pass,
+    // 失敗したテスト番号
+    Fail(u32),
+    Timeout,
+    Error(String),
+}
+
+impl Display for TestOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TestOutcome::Pass => write!(f, "PASS"),
+            TestOutcome::Fail(test_number) => write!(f, "FAIL (test {})", test_number),
+            TestOutcome::Timeout => write!(f, "TIMEOUT"),
+            TestOutcome::Error(message) => write!(f, "ERROR ({})", message),
+        }
+    }
+}
+
+pub struct TestReport {
+    pub name: String,
+    pub outcome: TestOutcome,
+}
+
+// ディレクトリ内のriscv-testsのバイナリを名前順に列挙する
+pub fn find_test_images(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut images: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| TEST_PREFIXES.iter().any(|p| name.starts_with(p)))
+        })
+        .collect();
+    images.sort();
+
+    Ok(images)
+}
+
+// 新しいComputerでテストバイナリを1つ実行する
+pub fn run_test_image(path: &Path, extensions: Extensions, step_limit: u64) -> TestOutcome {
+    let processor = RiscVUIProcessor::with_extensions(extensions);
+    let mut computer = Computer::new(processor, Bus::default());
+
+    let start_address = computer.bus().config().reset_vector();
+    if let Err(error) = computer.load_from_file(start_address, path) {
+        return TestOutcome::Error(error.to_string());
+    }
+
+    match computer.run_with_step_limit(Some(step_limit)) {
+        Ok(RunResult::Exit(0)) => TestOutcome::Pass,
+        Ok(RunResult::Exit(test_number)) => TestOutcome::Fail(test_number),
+        Ok(RunResult::StepLimit) => TestOutcome::Timeout,
+        Ok(RunResult::Ecall) => TestOutcome::Error("ECALL without trap handler".to_string()),
+        Err(error) => TestOutcome::Error(error.to_string()),
+    }
+}
+
+pub fn run_test_suite(
+    dir: &Path,
+    extensions: Extensions,
+    step_limit: u64,
+) -> std::io::Result<Vec<TestReport>> {
+    Ok(find_test_images(dir)?
+        .iter()
+        .map(|path| TestReport {
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            outcome: run_test_image(path, extensions, step_limit),
+        })
+        .collect())
+}
+
+pub fn print_summary(reports: &[TestReport]) {
+    let name_width = reports.iter().map(|r| r.name.len()).max().unwrap_or(0);
+
+    for report in reports {
+        println!("{:<width$}  {}", report.name, report.outcome, width = name_width);
+    }
+
+    let count = |f: fn(&TestOutcome) -> bool| reports.iter().filter(|r| f(&r.outcome)).count();
+    println!();
+    println!(
+        "{} passed, {} failed, {} timed out, {} errors",
+        count(|o| *o == TestOutcome::Pass),
+        count(|o| matches!(o, TestOutcome::Fail(_))),
+        count(|o| *o == TestOutcome::Timeout),
+        count(|o| matches!(o, TestOutcome::Error(_))),
+    );
+}
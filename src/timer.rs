@@ -0,0 +1,43 @@
+// mtimeを刻むタイマー
+// timebaseサイクルごとにmtimeが1進む。timeCSRとCLINTはこの値を共有する
+#[derive(Debug, Clone, Copy)]
+pub struct Timer {
+    mtime: u64,
+    timebase: u32,
+    cycles: u32,
+}
+
+pub const DEFAULT_TIMEBASE: u32 = 1;
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIMEBASE)
+    }
+}
+
+impl Timer {
+    pub fn new(timebase: u32) -> Self {
+        Self {
+            mtime: 0,
+            timebase: timebase.max(1),
+            cycles: 0,
+        }
+    }
+
+    pub fn mtime(&self) -> u64 {
+        self.mtime
+    }
+
+    pub fn set_mtime(&mut self, mtime: u64) {
+        self.mtime = mtime;
+    }
+
+    // 1サイクル進める
+    pub fn tick(&mut self) {
+        self.cycles += 1;
+        if self.cycles >= self.timebase {
+            self.cycles = 0;
+            self.mtime = self.mtime.wrapping_add(1);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt::Arguments;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TraceError {
+    #[error("Unknown trace category: {0}")]
+    UnknownCategory(String),
+
+    #[error("Unknown trace level: {0}")]
+    UnknownLevel(String),
+}
+
+// トレースの種類。カテゴリごとに出力するレベルを選べる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Fetch,
+    Decode,
+    Exec,
+    Mem,
+    Csr,
+    Trap,
+    Device,
+}
+
+impl Category {
+    pub const ALL: [Category; 7] = [
+        Category::Fetch,
+        Category::Decode,
+        Category::Exec,
+        Category::Mem,
+        Category::Csr,
+        Category::Trap,
+        Category::Device,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::Fetch => "fetch",
+            Category::Decode => "decode",
+            Category::Exec => "exec",
+            Category::Mem => "mem",
+            Category::Csr => "csr",
+            Category::Trap => "trap",
+            Category::Device => "device",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|category| category.name() == name)
+    }
+}
+
+// Infoはトラップなどの出来事、Debugは命令ごとの情報、Traceはレジスタの全ダンプなど
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Info = 1,
+    Debug = 2,
+    Trace = 3,
+}
+
+impl Level {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+}
+
+// カテゴリごとに出力する最大のレベル。0なら出力しない
+static LEVELS: [AtomicU8; Category::ALL.len()] = [const { AtomicU8::new(0) }; Category::ALL.len()];
+
+// Noneなら標準エラー出力に書く
+static OUTPUT: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+// 無効なときはこの判定だけで済むので、メッセージの整形は行わない
+#[inline]
+pub fn enabled(category: Category, level: Level) -> bool {
+    LEVELS[category as usize].load(Ordering::Relaxed) >= level as u8
+}
+
+pub fn set_level(category: Category, level: Option<Level>) {
+    LEVELS[category as usize].store(level.map_or(0, |level| level as u8), Ordering::Relaxed);
+}
+
+pub fn disable_all() {
+    for category in Category::ALL {
+        set_level(category, None);
+    }
+}
+
+// "fetch,mem=trace,all=info" の形式でカテゴリとレベルを指定する
+// レベルを省略したカテゴリはDebugになる。後に書いたものが優先される
+pub fn configure(spec: &str) -> Result<(), TraceError> {
+    let mut levels = Vec::new();
+    for item in spec.split(',').filter(|item| !item.is_empty()) {
+        let (name, level) = match item.split_once('=') {
+            Some((name, level)) => (
+                name,
+                Level::parse(level).ok_or_else(|| TraceError::UnknownLevel(level.to_string()))?,
+            ),
+            None => (item, Level::Debug),
+        };
+
+        match name {
+            "all" => levels.extend(Category::ALL.map(|category| (category, level))),
+            _ => levels.push((
+                Category::parse(name)
+                    .ok_or_else(|| TraceError::UnknownCategory(name.to_string()))?,
+                level,
+            )),
+        }
+    }
+
+    for (category, level) in levels {
+        set_level(category, Some(level));
+    }
+
+    Ok(())
+}
+
+pub fn set_output(output: Box<dyn Write + Send>) {
+    *lock_output() = Some(output);
+}
+
+// 出力先をバッファしている場合に備えて、終了前に呼ぶ
+pub fn flush() {
+    if let Some(output) = lock_output().as_mut() {
+        let _ = output.flush();
+    }
+}
+
+#[doc(hidden)]
+pub fn write(category: Category, args: Arguments) {
+    let line = format!("[{}] {}\n", category.name(), args);
+
+    let _ = match lock_output().as_mut() {
+        Some(output) => output.write_all(line.as_bytes()),
+        None => io::stderr().write_all(line.as_bytes()),
+    };
+}
+
+fn lock_output() -> std::sync::MutexGuard<'static, Option<Box<dyn Write + Send>>> {
+    OUTPUT.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+// trace!(Fetch, Debug, "Fetch: 0x{:0>8x}", inst) のように使う
+// 引数はカテゴリとレベルが有効なときだけ評価される
+#[macro_export]
+macro_rules! trace {
+    ($category:ident, $level:ident, $($arg:tt)*) => {
+        if $crate::trace::enabled(
+            $crate::trace::Category::$category,
+            $crate::trace::Level::$level,
+        ) {
+            $crate::trace::write($crate::trace::Category::$category, format_args!($($arg)*));
+        }
+    };
+}
@@ -0,0 +1,240 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::{device::Device, processor::ProcessorError};
+
+// NS16550A互換のUART
+// 送信したデータはホストの標準出力に書き、標準入力から読んだデータを受信FIFOに入れる
+pub const UART_BASE: u32 = 0x1000_0000;
+pub const UART_SIZE: u32 = 0x100;
+
+// PLICの割り込みソース番号
+pub const UART_IRQ: u32 = 10;
+
+const RBR_THR_DLL: u32 = 0;
+const IER_DLM: u32 = 1;
+const IIR_FCR: u32 = 2;
+const LCR: u32 = 3;
+const MCR: u32 = 4;
+const LSR: u32 = 5;
+const MSR: u32 = 6;
+const SCR: u32 = 7;
+
+const IER_RX_AVAILABLE: u8 = 1 << 0;
+const IER_THR_EMPTY: u8 = 1 << 1;
+
+const IIR_NO_INTERRUPT: u8 = 0x01;
+const IIR_THR_EMPTY: u8 = 0x02;
+const IIR_RX_AVAILABLE: u8 = 0x04;
+const IIR_FIFO_ENABLED: u8 = 0xc0;
+
+const LCR_DLAB: u8 = 1 << 7;
+
+const LSR_DATA_READY: u8 = 1 << 0;
+const LSR_THR_EMPTY: u8 = 1 << 5;
+const LSR_TRANSMITTER_EMPTY: u8 = 1 << 6;
+
+#[derive(Debug)]
+pub struct Uart {
+    rx: VecDeque<u8>,
+    input: Option<Receiver<u8>>,
+    // Noneならホストの標準出力に書く
+    output: Option<Vec<u8>>,
+
+    ier: u8,
+    lcr: u8,
+    mcr: u8,
+    scr: u8,
+    dll: u8,
+    dlm: u8,
+    // 送信が終わったことを通知する割り込み。IIRを読むかTHRに書くと消える
+    thr_empty_interrupt: bool,
+}
+
+impl Default for Uart {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Uart {
+    pub fn new() -> Self {
+        Self {
+            rx: VecDeque::new(),
+            input: None,
+            output: None,
+            ier: 0,
+            lcr: 0,
+            mcr: 0,
+            scr: 0,
+            dll: 0,
+            dlm: 0,
+            thr_empty_interrupt: false,
+        }
+    }
+
+    // ホストの標準入力を別スレッドで読み、ブロックせずに受信FIFOへ渡す
+    pub fn connect_stdin(&mut self) {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut stdin = io::stdin();
+            let mut buffer = [0u8; 256];
+            // EOFかエミュレータ側が終了したら止める
+            while let Ok(length @ 1..) = stdin.read(&mut buffer) {
+                if buffer[..length]
+                    .iter()
+                    .any(|&byte| sender.send(byte).is_err())
+                {
+                    break;
+                }
+            }
+        });
+
+        self.input = Some(receiver);
+    }
+
+    // 送信データを標準出力ではなくバッファに溜める
+    pub fn capture_output(&mut self) {
+        self.output = Some(Vec::new());
+    }
+
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.output.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    // ホスト側から受信データを入れる
+    pub fn receive(&mut self, data: &[u8]) {
+        self.rx.extend(data);
+    }
+
+    // 標準入力から届いたデータを受信FIFOに移す
+    pub fn poll(&mut self) {
+        if let Some(input) = &self.input {
+            self.rx.extend(input.try_iter());
+        }
+    }
+
+    pub fn is_interrupting(&self) -> bool {
+        self.interrupt_id() != IIR_NO_INTERRUPT
+    }
+
+    fn interrupt_id(&self) -> u8 {
+        if self.ier & IER_RX_AVAILABLE != 0 && !self.rx.is_empty() {
+            IIR_RX_AVAILABLE
+        } else if self.ier & IER_THR_EMPTY != 0 && self.thr_empty_interrupt {
+            IIR_THR_EMPTY
+        } else {
+            IIR_NO_INTERRUPT
+        }
+    }
+
+    // offsetはUART_BASEからのオフセット。RBRやIIRは読むと状態が変わる
+    pub fn read(&mut self, offset: u32) -> u8 {
+        let dlab = self.lcr & LCR_DLAB != 0;
+
+        match offset {
+            RBR_THR_DLL if dlab => self.dll,
+            RBR_THR_DLL => self.rx.pop_front().unwrap_or(0),
+            IER_DLM if dlab => self.dlm,
+            IER_DLM => self.ier,
+            IIR_FCR => {
+                let id = self.interrupt_id();
+                if id == IIR_THR_EMPTY {
+                    self.thr_empty_interrupt = false;
+                }
+                id | IIR_FIFO_ENABLED
+            }
+            LCR => self.lcr,
+            MCR => self.mcr,
+            // 送信は即座に終わるので常に送信可能
+            LSR => {
+                let data_ready = if self.rx.is_empty() {
+                    0
+                } else {
+                    LSR_DATA_READY
+                };
+                LSR_THR_EMPTY | LSR_TRANSMITTER_EMPTY | data_ready
+            }
+            MSR => 0,
+            SCR => self.scr,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, offset: u32, value: u8) {
+        let dlab = self.lcr & LCR_DLAB != 0;
+
+        match offset {
+            RBR_THR_DLL if dlab => self.dll = value,
+            RBR_THR_DLL => {
+                self.transmit(value);
+                self.thr_empty_interrupt = true;
+            }
+            IER_DLM if dlab => self.dlm = value,
+            IER_DLM => {
+                // 送信割り込みを有効にした時点で送信バッファは空
+                if value & IER_THR_EMPTY != 0 && self.ier & IER_THR_EMPTY == 0 {
+                    self.thr_empty_interrupt = true;
+                }
+                self.ier = value & 0x0f;
+            }
+            // FIFOは常に有効として扱う
+            IIR_FCR => (),
+            LCR => self.lcr = value,
+            MCR => self.mcr = value,
+            SCR => self.scr = value,
+            _ => (),
+        }
+    }
+
+    fn transmit(&mut self, value: u8) {
+        match &mut self.output {
+            Some(output) => output.push(value),
+            None => {
+                let mut stdout = io::stdout();
+                let _ = stdout.write_all(&[value]);
+                let _ = stdout.flush();
+            }
+        }
+    }
+}
+
+// レジスタは8bit幅。それより広いアクセスは下位バイトだけを使う
+impl Device for Uart {
+    fn read8(&mut self, offset: u32) -> Result<u8, ProcessorError> {
+        Ok(self.read(offset))
+    }
+
+    fn read16(&mut self, offset: u32) -> Result<u16, ProcessorError> {
+        Ok(self.read(offset) as u16)
+    }
+
+    fn read32(&mut self, offset: u32) -> Result<u32, ProcessorError> {
+        Ok(self.read(offset) as u32)
+    }
+
+    fn write8(&mut self, offset: u32, value: u8) -> Result<(), ProcessorError> {
+        self.write(offset, value);
+        Ok(())
+    }
+
+    fn write16(&mut self, offset: u32, value: u16) -> Result<(), ProcessorError> {
+        self.write(offset, value as u8);
+        Ok(())
+    }
+
+    fn write32(&mut self, offset: u32, value: u32) -> Result<(), ProcessorError> {
+        self.write(offset, value as u8);
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        self.poll();
+    }
+
+    fn is_interrupting(&self) -> bool {
+        Uart::is_interrupting(self)
+    }
+}
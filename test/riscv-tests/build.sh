@@ -0,0 +1,25 @@
+#!/bin/sh
+# rv32*/にあるテストをビルドしてtest/rv32*-p-*を作り直す
+# 必要なもの: cppとRISC-V向けのasとld
+# 例えばLLVMを使うときは
+#   AS="llvm-mc -triple=riscv32 -mattr=+m,+a -filetype=obj" LD=ld.lld ./build.sh
+set -eu
+
+RISCV_PREFIX=${RISCV_PREFIX:-riscv32-unknown-elf-}
+AS=${AS:-"${RISCV_PREFIX}as -march=rv32ima_zicsr_zifencei -mabi=ilp32"}
+LD=${LD:-"${RISCV_PREFIX}ld"}
+
+cd "$(dirname "$0")"
+work=$(mktemp -d)
+trap 'rm -rf "$work"' EXIT
+
+for src in rv32*/*.S; do
+    suite=${src%%/*}
+    name=$(basename "$src" .S)
+    out="$suite-p-$name"
+
+    cpp -x assembler-with-cpp -P -nostdinc -undef -Ienv -Imacros "$src" |
+        $AS -o "$work/$out.o" -
+    $LD -static -nostdlib -T env/link.ld "$work/$out.o" -o "../$out"
+    echo "$out"
+done
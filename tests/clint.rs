@@ -0,0 +1,78 @@
+mod common;
+
+use common::{
+    addi, computer_with_program, csr, lui, lw, program_with_recording_handler, sw, CSRRS, CSRRW,
+};
+use simple_riscv::computer::RunResult;
+use simple_riscv::processor::riscv::rv32ui::cs_register::{MIE, MSTATUS};
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+
+#[test]
+fn timer_interrupt_fires_when_mtime_reaches_mtimecmp() {
+    let program = program_with_recording_handler(&[
+        lui(4, 0x200c),
+        lw(10, 4, -8), // mtime
+        addi(10, 10, 20),
+        lui(3, 0x2004),
+        sw(0, 3, 4),  // mtimecmp(上位)
+        sw(10, 3, 0), // mtimecmp(下位)
+        addi(1, 0, 1 << 7),
+        csr(CSRRW, 0, 1, MIE),
+        addi(1, 0, 1 << 3),
+        csr(CSRRS, 0, 1, MSTATUS),
+    ]);
+
+    let mut computer = computer_with_program(Extensions::rv32i(), &program);
+    assert_eq!(
+        computer.run_with_step_limit(Some(1000)).ok(),
+        Some(RunResult::StepLimit)
+    );
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(5), 0x8000_0007);
+    assert_eq!(xregs.read(7), 0x8000_0034);
+}
+
+#[test]
+fn software_interrupt_is_raised_by_msip() {
+    let program = program_with_recording_handler(&[
+        addi(1, 0, 1 << 3),
+        csr(CSRRW, 0, 1, MIE),
+        csr(CSRRS, 0, 1, MSTATUS),
+        lui(2, 0x2000),
+        addi(7, 0, 1),
+        sw(7, 2, 0), // msip
+    ]);
+
+    let mut computer = computer_with_program(Extensions::rv32i(), &program);
+    assert_eq!(
+        computer.run_with_step_limit(Some(1000)).ok(),
+        Some(RunResult::StepLimit)
+    );
+
+    assert_eq!(computer.processor().xregs.read(5), 0x8000_0003);
+}
+
+#[test]
+fn interrupts_are_masked_without_mstatus_mie() {
+    let program = program_with_recording_handler(&[
+        addi(1, 0, 1 << 3),
+        csr(CSRRW, 0, 1, MIE),
+        lui(2, 0x2000),
+        addi(7, 0, 1),
+        sw(7, 2, 0),
+        lw(8, 2, 0),
+        addi(10, 0, 42),
+    ]);
+
+    let mut computer = computer_with_program(Extensions::rv32i(), &program);
+    assert_eq!(
+        computer.run_with_step_limit(Some(1000)).ok(),
+        Some(RunResult::StepLimit)
+    );
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(5), 0);
+    assert_eq!(xregs.read(8), 1);
+    assert_eq!(xregs.read(10), 42);
+}
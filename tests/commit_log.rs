@@ -0,0 +1,120 @@
+mod common;
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use common::{addi, amo, computer_with_processor, lui, lw, program_bytes, sb, sw, ECALL, NOP};
+use simple_riscv::bus::Bus;
+use simple_riscv::computer::RunResult;
+use simple_riscv::processor::riscv::rv32ui::commit_log::Commit;
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+use simple_riscv::processor::riscv::rv32ui::trap::Privilege;
+use simple_riscv::processor::riscv::rv32ui::writeback::WritebackResult;
+use simple_riscv::processor::riscv::rv32ui::RiscVUIProcessor;
+
+const AMOADD: u32 = 0b00000;
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn run_with_commit_log(extensions: Extensions, program: &[u32]) -> Vec<String> {
+    let buffer = SharedBuffer::default();
+    let mut processor = RiscVUIProcessor::with_extensions(extensions);
+    processor.commit_log = Some(Box::new(buffer.clone()));
+
+    let mut computer = computer_with_processor(processor, Bus::default(), program_bytes(program));
+    assert_eq!(computer.run().ok(), Some(RunResult::Ecall));
+
+    let output = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+    output.lines().map(str::to_string).collect()
+}
+
+#[test]
+fn commit_log_records_register_and_memory_writes() {
+    let program = [
+        addi(1, 0, 5),
+        lui(2, 0x80001),
+        sw(1, 2, 0),
+        lw(5, 2, 0),
+        sb(1, 2, 3),
+        NOP,
+        ECALL,
+    ];
+    let lines = run_with_commit_log(Extensions::rv32i(), &program);
+
+    assert_eq!(
+        lines,
+        [
+            format!(
+                "core   0: 3 0x80000000 (0x{:0>8x}) x1  0x00000005",
+                program[0]
+            ),
+            format!(
+                "core   0: 3 0x80000004 (0x{:0>8x}) x2  0x80001000",
+                program[1]
+            ),
+            format!(
+                "core   0: 3 0x80000008 (0x{:0>8x}) mem 0x80001000 0x00000005",
+                program[2]
+            ),
+            format!(
+                "core   0: 3 0x8000000c (0x{:0>8x}) x5  0x00000005 mem 0x80001000",
+                program[3]
+            ),
+            format!(
+                "core   0: 3 0x80000010 (0x{:0>8x}) mem 0x80001003 0x05",
+                program[4]
+            ),
+            // x0への書き込みは記録しない。例外になったECALLはリタイアしていないので出力されない
+            format!("core   0: 3 0x80000014 (0x{:0>8x})", program[5]),
+        ]
+    );
+}
+
+#[test]
+fn commit_log_records_amo_load_and_store() {
+    let program = [
+        addi(1, 0, 5),
+        lui(2, 0x80001),
+        sw(1, 2, 0),
+        amo(AMOADD, 6, 2, 1),
+        ECALL,
+    ];
+    let lines = run_with_commit_log(Extensions::parse("rv32ia").unwrap(), &program);
+
+    assert_eq!(
+        lines[3],
+        format!(
+            "core   0: 3 0x8000000c (0x{:0>8x}) x6  0x00000005 mem 0x80001000 mem 0x80001000 0x0000000a",
+            program[3]
+        )
+    );
+}
+
+#[test]
+fn compressed_instruction_is_logged_as_16_bits() {
+    let commit = Commit {
+        privilege: Privilege::Supervisor,
+        pc: 0x8000_0010,
+        inst: 0x4505, // c.li a0, 1
+        inst_len: 2,
+        rd: Some((10, 1)),
+        memory: WritebackResult::default(),
+    };
+
+    assert_eq!(
+        commit.to_string(),
+        "core   0: 1 0x80000010 (0x4505) x10 0x00000001"
+    );
+}
@@ -0,0 +1,143 @@
+#![allow(dead_code)]
+
+use simple_riscv::bus::Bus;
+use simple_riscv::computer::Computer;
+use simple_riscv::processor::riscv::rv32ui::cs_register::{MCAUSE, MEPC, MTVAL, MTVEC};
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+use simple_riscv::processor::riscv::rv32ui::RiscVUIProcessor;
+
+pub const ECALL: u32 = 0x0000_0073;
+pub const MRET: u32 = 0x3020_0073;
+pub const SRET: u32 = 0x1020_0073;
+pub const NOP: u32 = 0x0000_0013;
+// jal x0, 0
+pub const JAL_SELF: u32 = 0x0000_006f;
+
+pub const CSRRW: u32 = 0b001;
+pub const CSRRS: u32 = 0b010;
+
+pub fn r_type(funct7: u32, funct3: u32, rd: u32, rs1: u32, rs2: u32) -> u32 {
+    funct7 << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | 0b0110011
+}
+
+pub fn amo(funct5: u32, rd: u32, rs1: u32, rs2: u32) -> u32 {
+    funct5 << 27 | rs2 << 20 | rs1 << 15 | 0b010 << 12 | rd << 7 | 0b0101111
+}
+
+pub fn addi(rd: u32, rs1: u32, imm: i32) -> u32 {
+    ((imm as u32) & 0xfff) << 20 | rs1 << 15 | rd << 7 | 0b0010011
+}
+
+pub fn lui(rd: u32, imm: u32) -> u32 {
+    imm << 12 | rd << 7 | 0b0110111
+}
+
+pub fn lw(rd: u32, rs1: u32, imm: i32) -> u32 {
+    ((imm as u32) & 0xfff) << 20 | rs1 << 15 | 0b010 << 12 | rd << 7 | 0b0000011
+}
+
+pub fn lbu(rd: u32, rs1: u32, imm: i32) -> u32 {
+    ((imm as u32) & 0xfff) << 20 | rs1 << 15 | 0b100 << 12 | rd << 7 | 0b0000011
+}
+
+pub fn sb(rs2: u32, rs1: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    (imm >> 5 & 0x7f) << 25 | rs2 << 20 | rs1 << 15 | (imm & 0x1f) << 7 | 0b0100011
+}
+
+pub fn sw(rs2: u32, rs1: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    (imm >> 5 & 0x7f) << 25 | rs2 << 20 | rs1 << 15 | 0b010 << 12 | (imm & 0x1f) << 7 | 0b0100011
+}
+
+pub fn jalr(rd: u32, rs1: u32, imm: i32) -> u32 {
+    ((imm as u32) & 0xfff) << 20 | rs1 << 15 | rd << 7 | 0b1100111
+}
+
+fn b_type(funct3: u32, rs1: u32, rs2: u32, offset: i32) -> u32 {
+    let imm = offset as u32;
+    (imm >> 12 & 1) << 31
+        | (imm >> 5 & 0x3f) << 25
+        | rs2 << 20
+        | rs1 << 15
+        | funct3 << 12
+        | (imm >> 1 & 0xf) << 8
+        | (imm >> 11 & 1) << 7
+        | 0b1100011
+}
+
+pub fn beq(rs1: u32, rs2: u32, offset: i32) -> u32 {
+    b_type(0b000, rs1, rs2, offset)
+}
+
+pub fn blt(rs1: u32, rs2: u32, offset: i32) -> u32 {
+    b_type(0b100, rs1, rs2, offset)
+}
+
+pub fn csr(funct3: u32, rd: u32, rs1: u32, csr: u32) -> u32 {
+    csr << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | 0b1110011
+}
+
+pub const HANDLER_OFFSET: u32 = 0x80;
+
+// mtvecを0x80000080に向けてからbodyを実行し、最後はその場でループするプログラムを作る
+// ハンドラはmcause/mtval/mepcをx5/x6/x7に記録してx8でトラップの回数を数える
+// 例外なら次の命令に戻り、割り込みならハンドラの中でループする
+pub fn program_with_recording_handler(body: &[u32]) -> Vec<u32> {
+    let mut program = vec![
+        lui(1, 0x80000),
+        addi(1, 1, HANDLER_OFFSET as i32),
+        csr(CSRRW, 0, 1, MTVEC),
+    ];
+    program.extend_from_slice(body);
+    program.push(JAL_SELF);
+    assert!(program.len() <= HANDLER_OFFSET as usize / 4);
+    program.resize(HANDLER_OFFSET as usize / 4, NOP);
+    program.extend([
+        csr(CSRRS, 5, 0, MCAUSE),
+        csr(CSRRS, 6, 0, MTVAL),
+        csr(CSRRS, 7, 0, MEPC),
+        addi(8, 8, 1),
+        blt(5, 0, 0),
+        addi(31, 7, 4),
+        csr(CSRRW, 0, 31, MEPC),
+        MRET,
+    ]);
+    program
+}
+
+// 命令列をメモリに置くためのリトルエンディアンのバイト列にする
+pub fn program_bytes(program: &[u32]) -> Vec<u8> {
+    program.iter().flat_map(|inst| inst.to_le_bytes()).collect()
+}
+
+// DRAMの先頭にプログラムを配置したComputerを作る
+pub fn computer_with_program(
+    extensions: Extensions,
+    program: &[u32],
+) -> Computer<RiscVUIProcessor> {
+    computer_with_bytes(extensions, program_bytes(program))
+}
+
+pub fn computer_with_bytes(extensions: Extensions, bytes: Vec<u8>) -> Computer<RiscVUIProcessor> {
+    computer_with_bus(extensions, Bus::default(), bytes)
+}
+
+pub fn computer_with_bus(
+    extensions: Extensions,
+    bus: Bus,
+    bytes: Vec<u8>,
+) -> Computer<RiscVUIProcessor> {
+    computer_with_processor(RiscVUIProcessor::with_extensions(extensions), bus, bytes)
+}
+
+// バスの設定のリセットベクタにプログラムを置く
+pub fn computer_with_processor(
+    processor: RiscVUIProcessor,
+    mut bus: Bus,
+    bytes: Vec<u8>,
+) -> Computer<RiscVUIProcessor> {
+    bus.load8(bus.config().reset_vector(), bytes).ok().unwrap();
+
+    Computer::new(processor, bus)
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use common::{addi, computer_with_bus, lui, lw, program_bytes, sw, ECALL};
+use simple_riscv::bus::{Bus, DRAM_BASE};
+use simple_riscv::computer::{Computer, RunResult};
+use simple_riscv::config::{ConfigError, MachineConfig, MemoryBackend, DEFAULT_DRAM_SIZE};
+use simple_riscv::memory::Memory;
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+use simple_riscv::processor::riscv::rv32ui::RiscVUIProcessor;
+
+fn computer_with_config(config: &MachineConfig, program: &[u32]) -> Computer<RiscVUIProcessor> {
+    computer_with_bus(
+        Extensions::rv32i(),
+        Bus::new(config),
+        program_bytes(program),
+    )
+}
+
+#[test]
+fn default_config_matches_previous_layout() {
+    let config = MachineConfig::default();
+
+    assert_eq!(config.reset_vector(), DRAM_BASE);
+    assert_eq!(
+        config.initial_stack_pointer(),
+        DRAM_BASE + DEFAULT_DRAM_SIZE
+    );
+}
+
+#[test]
+fn small_memory_sets_pc_and_stack_pointer() {
+    let config = MachineConfig::new(0x4000_0000, 64 * 1024).unwrap();
+    // スタックの一番上にストアして読み戻す
+    let program = [sw(2, 2, -4), lw(3, 2, -4), ECALL];
+
+    let mut computer = computer_with_config(&config, &program);
+    assert_eq!(computer.run().ok(), Some(RunResult::Ecall));
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(2), 0x4001_0000);
+    assert_eq!(xregs.read(3), 0x4001_0000);
+    assert!(computer.bus_mut().read32(0x4001_0000).is_err());
+    assert!(computer.bus_mut().read32(DRAM_BASE).is_err());
+}
+
+#[test]
+fn additional_memory_region_is_mapped() {
+    let mut config = MachineConfig::default();
+    config.add_memory(0x2000_0000, 0x1000).unwrap();
+    let program = [
+        lui(1, 0x20000),
+        addi(2, 0, 0x5a),
+        sw(2, 1, 0x7fc),
+        lw(3, 1, 0x7fc),
+        ECALL,
+    ];
+
+    let mut computer = computer_with_config(&config, &program);
+    assert_eq!(computer.run().ok(), Some(RunResult::Ecall));
+
+    assert_eq!(computer.processor().xregs.read(3), 0x5a);
+    assert!(computer.bus_mut().read8(0x2000_1000).is_err());
+}
+
+#[test]
+fn invalid_regions_are_rejected() {
+    let mut config = MachineConfig::default();
+
+    assert_eq!(
+        config.add_memory(DRAM_BASE + 0x8_0000, 0x1000),
+        Err(ConfigError::OverlappingRegion {
+            base: DRAM_BASE + 0x8_0000,
+            size: 0x1000
+        })
+    );
+    // UARTと重なる
+    assert!(config.add_memory(0x1000_0000, 0x1000).is_err());
+    assert!(config.add_memory(0xffff_f000, 0x2000).is_err());
+    assert!(config.add_memory(0x2000_0000, 0).is_err());
+    assert!(MachineConfig::new(0x8000_0000, 0x8000_0000).is_ok());
+}
+
+#[test]
+fn large_memory_is_allocated_lazily() {
+    let mut config = MachineConfig::new(DRAM_BASE, 0x8000_0000).unwrap();
+    config.set_memory_backend(MemoryBackend::Sparse);
+    let mut bus = Bus::new(&config);
+    assert_eq!(bus.read32(0xffff_fffc).ok(), Some(0));
+
+    bus.write32(0xffff_fffc, 0xdead_beef).ok().unwrap();
+    assert_eq!(bus.read32(0xffff_fffc).ok(), Some(0xdead_beef));
+    assert!(matches!(bus.memory()[0], Memory::Sparse(_)));
+    assert_eq!(bus.touched_pages().collect::<Vec<_>>(), vec![0xffff_f000]);
+}
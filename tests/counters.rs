@@ -0,0 +1,120 @@
+mod common;
+
+use common::{
+    addi, computer_with_program, csr, lui, program_bytes, CSRRS, CSRRW, ECALL, JAL_SELF, MRET, NOP,
+};
+use simple_riscv::bus::Bus;
+use simple_riscv::computer::RunResult;
+use simple_riscv::processor::riscv::rv32ui::cs_register::{
+    CYCLE, INSTRET, INSTRETH, MCAUSE, MCOUNTEREN, MCOUNTINHIBIT, MEPC, MINSTRET, MINSTRETH,
+    MSTATUS, MTVEC, SCOUNTEREN, TIME,
+};
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+use simple_riscv::timer::Timer;
+
+#[test]
+fn instret_counts_retired_instructions() {
+    let mut computer = computer_with_program(
+        Extensions::rv32i(),
+        &[
+            csr(CSRRS, 10, 0, INSTRET),
+            NOP,
+            NOP,
+            csr(CSRRS, 11, 0, INSTRET),
+            csr(CSRRS, 12, 0, CYCLE),
+            ECALL,
+        ],
+    );
+    assert_eq!(computer.run().ok(), Some(RunResult::Ecall));
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(11) - xregs.read(10), 3);
+    assert_eq!(xregs.read(12), 4);
+}
+
+#[test]
+fn counters_split_into_high_and_low_halves() {
+    let mut computer = computer_with_program(
+        Extensions::rv32i(),
+        &[
+            addi(1, 0, -1),
+            addi(2, 0, 1),
+            // CSR命令で書き込んだ値が優先され、その命令では進まない
+            csr(CSRRW, 0, 1, MINSTRET),
+            csr(CSRRW, 0, 2, MINSTRETH),
+            csr(CSRRS, 10, 0, INSTRET),
+            csr(CSRRS, 11, 0, INSTRETH),
+            ECALL,
+        ],
+    );
+    assert_eq!(computer.run().ok(), Some(RunResult::Ecall));
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(10), 0xffff_ffff);
+    assert_eq!(xregs.read(11), 2);
+}
+
+#[test]
+fn mcountinhibit_stops_instret() {
+    let mut computer = computer_with_program(
+        Extensions::rv32i(),
+        &[
+            addi(1, 0, 0b100),
+            csr(CSRRW, 0, 1, MCOUNTINHIBIT),
+            csr(CSRRS, 10, 0, INSTRET),
+            NOP,
+            csr(CSRRS, 11, 0, INSTRET),
+            ECALL,
+        ],
+    );
+    assert_eq!(computer.run().ok(), Some(RunResult::Ecall));
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(10), xregs.read(11));
+}
+
+#[test]
+fn time_follows_the_timebase() {
+    let program = [NOP, NOP, NOP, NOP, NOP, NOP, csr(CSRRS, 10, 0, TIME), ECALL];
+
+    let mut bus = Bus::default();
+    bus.clint.timer = Timer::new(3);
+    let mut computer = common::computer_with_bus(Extensions::rv32i(), bus, program_bytes(&program));
+    assert_eq!(computer.run().ok(), Some(RunResult::Ecall));
+
+    assert_eq!(computer.processor().xregs.read(10), 2);
+}
+
+#[test]
+fn user_mode_counter_access_requires_mcounteren() {
+    let mut program = vec![
+        lui(1, 0x80000),
+        addi(1, 1, 0x40),
+        csr(CSRRW, 0, 1, MTVEC),
+        addi(2, 0, 0b001), // cycleのみ許可する
+        csr(CSRRW, 0, 2, MCOUNTEREN),
+        csr(CSRRW, 0, 2, SCOUNTEREN),
+        lui(1, 0x80000),
+        addi(1, 1, 0x80),
+        csr(CSRRW, 0, 1, MEPC),
+        MRET, // MPP = U
+    ];
+    program.resize(0x40 / 4, NOP);
+    program.extend([
+        csr(CSRRS, 5, 0, MCAUSE),
+        csr(CSRRS, 6, 0, MSTATUS),
+        JAL_SELF,
+    ]);
+    program.resize(0x80 / 4, NOP);
+    program.extend([csr(CSRRS, 10, 0, CYCLE), csr(CSRRS, 11, 0, INSTRET)]);
+
+    let mut computer = computer_with_program(Extensions::rv32i(), &program);
+    assert_eq!(
+        computer.run_with_step_limit(Some(100)).ok(),
+        Some(RunResult::StepLimit)
+    );
+    let xregs = &computer.processor().xregs;
+
+    assert_ne!(xregs.read(10), 0);
+    assert_eq!(xregs.read(5), 2);
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use common::{addi, computer_with_program, csr, program_with_recording_handler, CSRRS, CSRRW};
+use simple_riscv::computer::{Computer, RunResult};
+use simple_riscv::processor::riscv::rv32ui::cs_register::{
+    CYCLE, MEDELEG, MISA, MSTATUS, MVENDORID,
+};
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+use simple_riscv::processor::riscv::rv32ui::RiscVUIProcessor;
+
+// 不正命令のたびに共通のハンドラで次の命令に戻り、x8で回数を数える
+fn run_with_recording_handler(body: &[u32]) -> Computer<RiscVUIProcessor> {
+    let program = program_with_recording_handler(body);
+
+    let mut computer = computer_with_program(Extensions::all(), &program);
+    assert_eq!(
+        computer.run_with_step_limit(Some(1000)).ok(),
+        Some(RunResult::StepLimit)
+    );
+    computer
+}
+
+#[test]
+fn unimplemented_and_read_only_csrs_are_illegal() {
+    let computer = run_with_recording_handler(&[
+        csr(CSRRS, 10, 0, 0x7c0),     // 実装していないCSR
+        csr(CSRRW, 0, 1, MVENDORID),  // 読み取り専用CSRへの書き込み
+        csr(CSRRS, 11, 0, MVENDORID), // 読み出しは可能
+        csr(CSRRS, 12, 0, CYCLE),     // rs1 = x0 なら書き込まない
+    ]);
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(8), 2);
+    assert_eq!(xregs.read(5), 2);
+    assert_eq!(xregs.read(11), 0);
+}
+
+#[test]
+fn warl_fields_are_masked() {
+    let computer = run_with_recording_handler(&[
+        addi(2, 0, -1),
+        csr(CSRRW, 0, 2, MSTATUS),
+        csr(CSRRS, 10, 0, MSTATUS),
+        csr(CSRRW, 0, 2, MEDELEG),
+        csr(CSRRS, 11, 0, MEDELEG),
+        csr(CSRRW, 0, 2, MISA),
+        csr(CSRRS, 12, 0, MISA),
+    ]);
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(8), 0);
+    assert_eq!(xregs.read(10), 0x007e_19aa);
+    assert_eq!(xregs.read(11), 0xb3ff);
+    // RV32 + A, C, I, M, S, U
+    assert_eq!(xregs.read(12), 0x4014_1105);
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use common::{addi, computer_with_bus, lui, lw, program_bytes, sw, ECALL};
+use simple_riscv::bus::{Bus, DRAM_BASE};
+use simple_riscv::computer::RunResult;
+use simple_riscv::device::Device;
+use simple_riscv::plic::CONTEXT_MACHINE;
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+use simple_riscv::processor::ProcessorError;
+
+const DEVICE_BASE: u32 = 0x2000_0000;
+
+// 書き込まれた値を記録し、読むと書き込み回数を返すテスト用のデバイス
+#[derive(Debug, Default)]
+struct Recorder {
+    writes: Rc<RefCell<Vec<(u32, u8)>>>,
+    ticks: u32,
+}
+
+impl Device for Recorder {
+    fn read8(&mut self, _offset: u32) -> Result<u8, ProcessorError> {
+        Ok(self.writes.borrow().len() as u8)
+    }
+
+    fn write8(&mut self, offset: u32, value: u8) -> Result<(), ProcessorError> {
+        self.writes.borrow_mut().push((offset, value));
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        self.ticks += 1;
+    }
+
+    // 2サイクル目以降は割り込みを上げ続ける
+    fn is_interrupting(&self) -> bool {
+        self.ticks >= 2
+    }
+}
+
+#[test]
+fn guest_accesses_external_device() {
+    let writes = Rc::new(RefCell::new(Vec::new()));
+    let mut bus = Bus::default();
+    bus.map_device(
+        DEVICE_BASE,
+        0x100,
+        Box::new(Recorder {
+            writes: writes.clone(),
+            ticks: 0,
+        }),
+    )
+    .ok()
+    .unwrap();
+
+    let program = [
+        lui(1, DEVICE_BASE >> 12),
+        addi(2, 0, 0x1ab),
+        sw(2, 1, 0x10),
+        lw(3, 1, 0),
+        ECALL,
+    ];
+    let mut computer = computer_with_bus(Extensions::rv32i(), bus, program_bytes(&program));
+    assert_eq!(computer.run().ok(), Some(RunResult::Ecall));
+
+    // 32bitアクセスは既定で1byteずつに分かれる
+    assert_eq!(
+        *writes.borrow(),
+        vec![(0x10, 0xab), (0x11, 0x01), (0x12, 0x00), (0x13, 0x00)]
+    );
+    assert_eq!(computer.processor().xregs.read(3), 0x0404_0404);
+}
+
+#[test]
+fn overlapping_ranges_are_rejected() {
+    let mut bus = Bus::default();
+    let device = || Box::new(Recorder::default());
+
+    assert!(bus.map_device(DEVICE_BASE, 0x100, device()).is_ok());
+    assert!(bus.map_device(DEVICE_BASE + 0xff, 0x10, device()).is_err());
+    assert!(bus.map_device(DEVICE_BASE - 0x10, 0x11, device()).is_err());
+    assert!(bus.map_device(DRAM_BASE + 0x1000, 0x10, device()).is_err());
+    assert!(bus.map_device(DEVICE_BASE + 0x100, 0x10, device()).is_ok());
+    assert!(bus.map_device(0xffff_ff00, 0x100, device()).is_ok());
+    assert!(bus.map_device(0x3000_0000, 0, device()).is_err());
+}
+
+#[test]
+fn unmapped_access_reports_address() {
+    let mut bus = Bus::default();
+
+    let error = bus.read32(0x4000_0010).err().unwrap();
+    assert_eq!(error.to_string(), "Unmapped bus address 0x40000010");
+    // 領域の末尾をまたぐアクセスも割り当てられていない扱い
+    bus.map_device(DEVICE_BASE, 0x100, Box::new(Recorder::default()))
+        .ok()
+        .unwrap();
+    assert!(bus.write16(DEVICE_BASE + 0xff, 0).is_err());
+}
+
+#[test]
+fn device_interrupt_is_routed_to_plic() {
+    let mut bus = Bus::default();
+    assert!(bus
+        .map_device_with_interrupt(DEVICE_BASE, 0x100, Box::new(Recorder::default()), 32)
+        .is_err());
+    bus.map_device_with_interrupt(DEVICE_BASE, 0x100, Box::new(Recorder::default()), 3)
+        .ok()
+        .unwrap();
+
+    // priority[3] = 1, context 0でソース3を有効にする
+    bus.write32(0x0c00_0000 + 3 * 4, 1).ok().unwrap();
+    bus.write32(0x0c00_2000, 1 << 3).ok().unwrap();
+
+    bus.tick();
+    assert!(!bus.plic.is_interrupting(CONTEXT_MACHINE));
+    bus.tick();
+    assert!(bus.plic.is_interrupting(CONTEXT_MACHINE));
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use std::path::Path;
+
+use common::{addi, amo, beq, csr, lui, lw, sw, CSRRW, ECALL, MRET};
+use simple_riscv::elf::Elf;
+use simple_riscv::processor::riscv::rv32ui::cs_register::MSTATUS;
+use simple_riscv::processor::riscv::rv32ui::disasm::{
+    disassemble, disassemble_bytes, disassemble_text,
+};
+
+const AMOADD: u32 = 0b00000;
+
+#[test]
+fn instructions_use_abi_names_and_signed_immediates() {
+    assert_eq!(disassemble(addi(10, 11, -1), 0), "addi a0, a1, -1");
+    assert_eq!(disassemble(lw(5, 2, -4), 0), "lw t0, -4(sp)");
+    assert_eq!(disassemble(sw(1, 8, 8), 0), "sw ra, 8(s0)");
+    assert_eq!(disassemble(lui(10, 0x80001), 0), "lui a0, 0x80001");
+    assert_eq!(
+        disassemble(csr(CSRRW, 10, 11, MSTATUS), 0),
+        "csrrw a0, mstatus, a1"
+    );
+    assert_eq!(
+        disassemble(csr(CSRRW, 0, 5, 0x7c0), 0),
+        "csrrw zero, 0x7c0, t0"
+    );
+    assert_eq!(disassemble(ECALL, 0), "ecall");
+    assert_eq!(disassemble(MRET, 0), "mret");
+    assert_eq!(disassemble(0x0ff0_000f, 0), "fence iorw, iorw");
+}
+
+#[test]
+fn branch_targets_are_resolved_from_pc() {
+    assert_eq!(
+        disassemble(beq(10, 11, 0x40), 0x8000_1000),
+        "beq a0, a1, 0x80001040"
+    );
+    assert_eq!(
+        disassemble(beq(10, 0, -8), 0x8000_1000),
+        "beq a0, zero, 0x80000ff8"
+    );
+    // jal zero, +0x50
+    assert_eq!(
+        disassemble(0x0500_006f, 0x8000_0000),
+        "jal zero, 0x80000050"
+    );
+}
+
+#[test]
+fn extension_instructions_are_disassembled() {
+    assert_eq!(
+        disassemble(amo(AMOADD, 6, 2, 1), 0),
+        "amoadd.w t1, ra, (sp)"
+    );
+    assert_eq!(
+        disassemble(amo(AMOADD, 6, 2, 1) | 1 << 26, 0),
+        "amoadd.w.aq t1, ra, (sp)"
+    );
+    // c.li a0, 1 は展開した命令として表示する
+    assert_eq!(disassemble(0x4505, 0), "addi a0, zero, 1");
+    assert_eq!(disassemble(0xffff_ffff, 0), ".word 0xffffffff");
+    assert_eq!(disassemble(0x0000, 0), ".half 0x0000");
+}
+
+#[test]
+fn byte_stream_mixes_compressed_and_full_instructions() {
+    let mut bytes = vec![];
+    bytes.extend(0x4505u16.to_le_bytes());
+    bytes.extend(addi(10, 10, 2).to_le_bytes());
+    // 命令の途中で終わっている部分は読まない
+    bytes.extend([0x13, 0x05]);
+
+    let lines = disassemble_bytes(&bytes, 0x8000_0000);
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0].to_string(), "80000000: 4505      addi a0, zero, 1");
+    assert_eq!(lines[1].address, 0x8000_0002);
+    assert_eq!(lines[1].to_string(), "80000002: 00250513  addi a0, a0, 2");
+}
+
+#[test]
+fn executable_sections_of_elf_are_disassembled() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("test")
+        .join("rv32ui-p-add");
+    let data = std::fs::read(path).unwrap();
+    let elf = Elf::parse(&data).unwrap();
+
+    let sections = disassemble_text(&elf).unwrap();
+    assert_eq!(sections.len(), 1);
+    let (name, lines) = &sections[0];
+    assert_eq!(name, ".text.init");
+    assert_eq!(
+        lines[0].to_string(),
+        "80000000: 0500006f  jal zero, 0x80000050"
+    );
+    assert_eq!(lines[1].text, "csrrs t5, mcause, zero");
+}
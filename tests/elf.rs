@@ -0,0 +1,101 @@
+use simple_riscv::bus::{Bus, DRAM_BASE};
+use simple_riscv::computer::{Computer, LoadError};
+use simple_riscv::config::MachineConfig;
+use simple_riscv::elf::{Elf, ElfError, PT_LOAD, SHT_SYMTAB};
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+use simple_riscv::processor::riscv::rv32ui::RiscVUIProcessor;
+
+const EHDR_SIZE: usize = 52;
+const PHDR_SIZE: usize = 32;
+const SHDR_SIZE: usize = 40;
+const SHT_STRTAB: u32 = 3;
+
+fn put16(data: &mut [u8], offset: usize, value: u16) {
+    data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+// ヘッダテーブルを持たないELFヘッダだけのファイル
+fn elf_header() -> Vec<u8> {
+    let mut data = vec![0u8; EHDR_SIZE];
+    data[..4].copy_from_slice(b"\x7fELF");
+    data[4] = 1; // ELFCLASS32
+    data[5] = 1; // ELFDATA2LSB
+    data[6] = 1;
+    put16(&mut data, 16, 2); // ET_EXEC
+    put16(&mut data, 18, 243); // EM_RISCV
+    put32(&mut data, 20, 1);
+    put16(&mut data, 40, EHDR_SIZE as u16);
+    put16(&mut data, 42, PHDR_SIZE as u16);
+    put16(&mut data, 46, SHDR_SIZE as u16);
+    data
+}
+
+#[test]
+fn header_table_past_end_of_address_space_is_rejected() {
+    let mut data = elf_header();
+    put32(&mut data, 28, 0xffff_fff0);
+    put16(&mut data, 44, 2);
+
+    assert!(matches!(Elf::parse(&data), Err(ElfError::Truncated)));
+}
+
+#[test]
+fn symbol_table_with_short_entries_is_rejected() {
+    let mut data = elf_header();
+    let shoff = data.len();
+    data.resize(shoff + SHDR_SIZE * 3 + 0x10, 0);
+    put32(&mut data, 32, shoff as u32);
+    put16(&mut data, 48, 3);
+
+    let tables = (shoff + SHDR_SIZE * 3) as u32;
+    // 1: .strtab
+    let strtab = shoff + SHDR_SIZE;
+    put32(&mut data, strtab + 4, SHT_STRTAB);
+    put32(&mut data, strtab + 16, tables);
+    put32(&mut data, strtab + 20, 1);
+    // 2: エントリが8バイトの.symtab
+    let symtab = shoff + SHDR_SIZE * 2;
+    put32(&mut data, symtab + 4, SHT_SYMTAB);
+    put32(&mut data, symtab + 16, tables);
+    put32(&mut data, symtab + 20, 0x10);
+    put32(&mut data, symtab + 24, 1);
+    put32(&mut data, symtab + 36, 8);
+
+    let elf = Elf::parse(&data).unwrap();
+    assert!(matches!(
+        elf.symbols(),
+        Err(ElfError::InvalidSymbolEntrySize(8))
+    ));
+}
+
+#[test]
+fn bss_past_end_of_address_space_is_rejected() {
+    let mut data = elf_header();
+    let phoff = data.len();
+    data.resize(phoff + PHDR_SIZE + 0x1000, 0);
+    put32(&mut data, 28, phoff as u32);
+    put16(&mut data, 44, 1);
+
+    // ファイルの部分はアドレス空間の末尾にちょうど収まり、.bssがはみ出す
+    put32(&mut data, phoff, PT_LOAD);
+    put32(&mut data, phoff + 4, (phoff + PHDR_SIZE) as u32);
+    put32(&mut data, phoff + 12, 0xffff_f000);
+    put32(&mut data, phoff + 16, 0x1000);
+    put32(&mut data, phoff + 20, 0x1010);
+
+    let mut config = MachineConfig::new(DRAM_BASE, 0x1000).unwrap();
+    config.add_memory(0xffff_f000, 0x1000).unwrap();
+    let mut computer = Computer::new(
+        RiscVUIProcessor::with_extensions(Extensions::rv32i()),
+        Bus::new(&config),
+    );
+
+    assert!(matches!(
+        computer.load_elf(&data),
+        Err(LoadError::SegmentLoadError(0xffff_f000))
+    ));
+}
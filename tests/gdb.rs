@@ -0,0 +1,226 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+use common::{addi, computer_with_program, lui, lw, sw, ECALL, JAL_SELF};
+use simple_riscv::gdb::GdbStub;
+use simple_riscv::processor::riscv::rv32ui::cs_register::MSTATUS;
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+
+const MSTATUS_REGNUM: u32 = 65 + MSTATUS;
+
+// RSPのクライアント。パケットを送り、'+'を確認してから応答を読む
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn send_raw(&mut self, data: &[u8]) {
+        self.stream.write_all(data).unwrap();
+    }
+
+    fn send(&mut self, data: &str) {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        self.send_raw(format!("${}#{:0>2x}", data, checksum).as_bytes());
+        assert_eq!(self.read_byte(), b'+');
+    }
+
+    fn receive(&mut self) -> String {
+        while self.read_byte() != b'$' {}
+
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => data.push(byte),
+            }
+        }
+        let checksum = [self.read_byte(), self.read_byte()];
+        let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap();
+        assert_eq!(
+            checksum,
+            data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+        );
+        self.send_raw(b"+");
+
+        String::from_utf8(data).unwrap()
+    }
+
+    fn request(&mut self, data: &str) -> String {
+        self.send(data);
+        self.receive()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+}
+
+// 別スレッドでprogramを実行するスタブを立ち上げて接続する
+fn connect(program: Vec<u32>) -> (Client, JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut computer = computer_with_program(Extensions::rv32i(), &program);
+        GdbStub::new(&mut computer, stream).run().unwrap();
+    });
+
+    let stream = TcpStream::connect(address).unwrap();
+    stream.set_nodelay(true).unwrap();
+    (Client { stream }, server)
+}
+
+fn register(value: u32) -> String {
+    value
+        .to_le_bytes()
+        .iter()
+        .map(|byte| format!("{:0>2x}", byte))
+        .collect()
+}
+
+fn program() -> Vec<u32> {
+    vec![
+        addi(1, 0, 5),
+        lui(2, 0x80001),
+        sw(1, 2, 0),
+        lw(3, 2, 0),
+        JAL_SELF,
+    ]
+}
+
+#[test]
+fn registers_and_target_description_are_readable() {
+    let (mut client, server) = connect(program());
+
+    assert!(client
+        .request("qSupported:multiprocess+;swbreak+")
+        .contains("qXfer:features:read+"));
+    assert_eq!(client.request("?"), "S05");
+    // 知らないコマンドは先頭がASCIIでなくても空の応答を返す
+    assert_eq!(client.request("\u{e9}"), "");
+
+    // 'm'なら続きがあるので、'l'が返るまで分割して読む
+    let mut xml = String::new();
+    loop {
+        let chunk = client.request(&format!(
+            "qXfer:features:read:target.xml:{:x},400",
+            xml.len()
+        ));
+        xml.push_str(&chunk[1..]);
+        if chunk.starts_with('l') {
+            break;
+        }
+        assert!(chunk.starts_with('m'));
+    }
+    assert!(xml.contains("<architecture>riscv:rv32</architecture>"));
+    assert!(xml.contains("<reg name=\"a0\" bitsize=\"32\" type=\"int\" regnum=\"10\"/>"));
+    assert!(xml.contains(&format!(
+        "<reg name=\"mstatus\" bitsize=\"32\" type=\"int\" regnum=\"{}\"/>",
+        MSTATUS_REGNUM
+    )));
+    assert!(xml.ends_with("</target>\n"));
+
+    let registers = client.request("g");
+    assert_eq!(registers.len(), 33 * 8);
+    assert_eq!(&registers[32 * 8..], register(0x8000_0000));
+
+    assert_eq!(client.request("P5=efbeadde"), "OK");
+    assert_eq!(client.request("p5"), "efbeadde");
+    assert_eq!(
+        client.request(&format!("P{:x}=08000000", MSTATUS_REGNUM)),
+        "OK"
+    );
+    assert_eq!(
+        client.request(&format!("p{:x}", MSTATUS_REGNUM)),
+        register(0x8)
+    );
+
+    client.send("k");
+    server.join().unwrap();
+}
+
+#[test]
+fn memory_is_read_and_written_without_touching_devices() {
+    let (mut client, server) = connect(program());
+
+    assert_eq!(client.request("m80000000,4"), register(addi(1, 0, 5)));
+    assert_eq!(client.request("M80001000,4:78563412"), "OK");
+    assert_eq!(client.request("m80001000,4"), "78563412");
+    // PacketSizeに収まる0x800バイトまでしか返さない
+    assert_eq!(client.request("m80000000,10000").len(), 0x1000);
+    // 何も割り当てられていないアドレス
+    assert_eq!(client.request("m0,4"), "E01");
+    // デバイスは読むと状態が変わることがあるので読み書きしない
+    assert_eq!(client.request("m10000000,1"), "E01");
+    assert_eq!(client.request("M2000000,4:01000000"), "E01");
+
+    client.send("k");
+    server.join().unwrap();
+}
+
+#[test]
+fn step_breakpoint_and_watchpoint_stop_execution() {
+    let (mut client, server) = connect(program());
+
+    assert_eq!(client.request("s"), "S05");
+    assert_eq!(client.request("p1"), register(5));
+    assert_eq!(client.request("p20"), register(0x8000_0004));
+
+    assert_eq!(client.request("Z0,8000000c,4"), "OK");
+    assert_eq!(client.request("c"), "T05swbreak:;");
+    assert_eq!(client.request("p20"), register(0x8000_000c));
+    assert_eq!(client.request("z0,8000000c,4"), "OK");
+
+    // swは実行済みなので、次のlwで読み込みのウォッチポイントに当たる
+    assert_eq!(client.request("Z3,80001000,4"), "OK");
+    assert_eq!(client.request("c"), "T05rwatch:80001000;");
+    assert_eq!(client.request("p3"), register(5));
+    assert_eq!(client.request("z3,80001000,4"), "OK");
+
+    client.send("k");
+    server.join().unwrap();
+}
+
+#[test]
+fn write_watchpoint_reports_store_address() {
+    let (mut client, server) = connect(program());
+
+    // ウォッチポイントの先頭ではなく、swが書き込んだアドレスを返す
+    assert_eq!(client.request("Z2,80000ffc,8"), "OK");
+    assert_eq!(client.request("c"), "T05watch:80001000;");
+    assert_eq!(client.request("p20"), register(0x8000_000c));
+
+    client.send("k");
+    server.join().unwrap();
+}
+
+#[test]
+fn ctrl_c_interrupts_continue() {
+    let (mut client, server) = connect(program());
+
+    client.send("c");
+    client.send_raw(&[0x03]);
+    assert_eq!(client.receive(), "S02");
+    assert_eq!(client.request("p20"), register(0x8000_0010));
+
+    assert_eq!(client.request("D"), "OK");
+    server.join().unwrap();
+}
+
+#[test]
+fn ecall_to_host_stops_with_sigtrap() {
+    let (mut client, server) = connect(vec![addi(10, 0, 1), ECALL]);
+
+    // プログラムの終了ではないので、W00ではなくSIGTRAPで止まる
+    assert_eq!(client.request("c"), "S05");
+    assert_eq!(client.request("p20"), register(0x8000_0004));
+
+    client.send("k");
+    server.join().unwrap();
+}
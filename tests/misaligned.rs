@@ -0,0 +1,133 @@
+mod common;
+
+use common::{
+    addi, computer_with_bus, jalr, lui, lw, program_bytes, program_with_recording_handler, sw,
+};
+use simple_riscv::bus::{Bus, DRAM_BASE};
+use simple_riscv::computer::{Computer, RunResult};
+use simple_riscv::config::{MachineConfig, MisalignedAccess, DEFAULT_DRAM_SIZE};
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+use simple_riscv::processor::riscv::rv32ui::RiscVUIProcessor;
+use simple_riscv::uart::UART_BASE;
+
+// 共通のハンドラを置いたプログラムをconfigのマシンで実行する
+fn run_with_recording_handler(config: &MachineConfig, body: &[u32]) -> Computer<RiscVUIProcessor> {
+    let program = program_with_recording_handler(body);
+
+    let bus = Bus::new(config);
+    let mut computer = computer_with_bus(Extensions::rv32i(), bus, program_bytes(&program));
+    assert_eq!(
+        computer.run_with_step_limit(Some(1000)).ok(),
+        Some(RunResult::StepLimit)
+    );
+
+    computer
+}
+
+#[test]
+fn trap_policy_raises_address_misaligned() {
+    let mut config = MachineConfig::default();
+    config.set_misaligned_access(MisalignedAccess::Trap);
+
+    let computer = run_with_recording_handler(&config, &[lui(2, 0x80001), lw(3, 2, 0x102)]);
+    let xregs = &computer.processor().xregs;
+    assert_eq!(xregs.read(5), 4);
+    assert_eq!(xregs.read(6), 0x8000_1102);
+    assert_eq!(xregs.read(7), 0x8000_0010);
+
+    let computer = run_with_recording_handler(&config, &[lui(2, 0x80001), sw(0, 2, 0x101)]);
+    let xregs = &computer.processor().xregs;
+    assert_eq!(xregs.read(5), 6);
+    assert_eq!(xregs.read(6), 0x8000_1101);
+}
+
+#[test]
+fn emulate_policy_splits_across_devices() {
+    let mut config = MachineConfig::default();
+    config.add_memory(0x2000_0000, 0x1000).unwrap();
+    config.add_memory(0x2000_1000, 0x1000).unwrap();
+
+    let computer = run_with_recording_handler(
+        &config,
+        &[
+            lui(2, 0x20001),
+            lui(3, 0x12345),
+            addi(3, 3, 0x678),
+            sw(3, 2, -2),
+            lw(4, 2, -2),
+            lw(10, 2, -4),
+            lw(11, 2, 0),
+        ],
+    );
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(5), 0);
+    assert_eq!(xregs.read(4), 0x1234_5678);
+    assert_eq!(xregs.read(10), 0x5678_0000);
+    assert_eq!(xregs.read(11), 0x0000_1234);
+}
+
+#[test]
+fn access_past_end_of_memory_faults() {
+    let end = DRAM_BASE + DEFAULT_DRAM_SIZE;
+    let mut bus = Bus::default();
+    assert!(bus.read32(end - 4).is_ok());
+    assert!(bus.read32(end - 2).is_err());
+    assert!(bus.write32(end - 1, 0).is_err());
+
+    // 分割したアクセスの一部がメモリの外に出るとアクセスフォールト
+    let computer = run_with_recording_handler(
+        &MachineConfig::default(),
+        &[lui(2, end >> 12), lw(3, 2, -2)],
+    );
+    let xregs = &computer.processor().xregs;
+    assert_eq!(xregs.read(5), 5);
+    assert_eq!(xregs.read(6), end);
+
+    // ストアはメモリの中の部分も書き込まない
+    let computer = run_with_recording_handler(
+        &MachineConfig::default(),
+        &[
+            lui(2, end >> 12),
+            lui(3, 0x12345),
+            addi(3, 3, 0x678),
+            sw(3, 2, -2),
+            lw(4, 2, -4),
+        ],
+    );
+    let xregs = &computer.processor().xregs;
+    assert_eq!(xregs.read(5), 7);
+    assert_eq!(xregs.read(6), end);
+    assert_eq!(xregs.read(4), 0);
+}
+
+#[test]
+fn split_store_to_device_faults() {
+    let computer = run_with_recording_handler(
+        &MachineConfig::default(),
+        &[lui(2, UART_BASE >> 12), addi(3, 0, 0x41), sw(3, 2, 1)],
+    );
+    let xregs = &computer.processor().xregs;
+    assert_eq!(xregs.read(5), 7);
+    assert_eq!(xregs.read(6), UART_BASE + 1);
+}
+
+#[test]
+fn misaligned_jump_target_raises_exception_on_jump() {
+    let computer = run_with_recording_handler(
+        &MachineConfig::default(),
+        &[
+            lui(2, 0x80000),
+            addi(2, 2, 0x22),
+            addi(9, 0, 7),
+            jalr(9, 2, 0),
+        ],
+    );
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(5), 0);
+    assert_eq!(xregs.read(6), 0x8000_0022);
+    assert_eq!(xregs.read(7), 0x8000_0018);
+    // 例外になった命令はrdを書き換えない
+    assert_eq!(xregs.read(9), 7);
+}
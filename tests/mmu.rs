@@ -0,0 +1,118 @@
+mod common;
+
+use common::{addi, computer_with_bytes, csr, lui, lw, sw, CSRRS, CSRRW, JAL_SELF, MRET};
+use simple_riscv::computer::{Computer, RunResult};
+use simple_riscv::processor::riscv::rv32ui::cs_register::{
+    MCAUSE, MEPC, MSTATUS, MTVAL, MTVEC, SATP,
+};
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+use simple_riscv::processor::riscv::rv32ui::RiscVUIProcessor;
+
+const PTE_V: u32 = 1 << 0;
+const PTE_R: u32 = 1 << 1;
+const PTE_X: u32 = 1 << 3;
+const PTE_A: u32 = 1 << 6;
+
+const HANDLER: usize = 0x100;
+const ROOT_TABLE: usize = 0x2000;
+const LEAF_TABLE: usize = 0x3000;
+const CODE_PAGE: usize = 0x4000;
+const DATA_PAGE: usize = 0x5000;
+
+fn pte(physical_address: usize, flags: u32) -> u32 {
+    (0x8000_0000 + physical_address as u32) >> 12 << 10 | flags
+}
+
+fn put(image: &mut [u8], offset: usize, words: &[u32]) {
+    for (i, word) in words.iter().enumerate() {
+        image[offset + i * 4..offset + i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+}
+
+// 仮想アドレス0x1000にコード、0x2000に読み出し専用データを割り当て、
+// Sモードでentryから実行する。トラップはMモードのハンドラでmcause/mtvalをx5/x6に記録してループする
+fn computer_with_paging(entry: u32, code: &[u32]) -> Computer<RiscVUIProcessor> {
+    let mut image = vec![0u8; 0x6000];
+
+    put(
+        &mut image,
+        0,
+        &[
+            lui(1, 0x80000),
+            addi(1, 1, HANDLER as i32),
+            csr(CSRRW, 0, 1, MTVEC),
+            lui(1, 0x80080),
+            addi(1, 1, (ROOT_TABLE >> 12) as i32),
+            csr(CSRRW, 0, 1, SATP),
+            lui(1, 0x1),
+            addi(1, 1, -0x800), // MPP = S
+            csr(CSRRS, 0, 1, MSTATUS),
+            lui(1, entry >> 12),
+            csr(CSRRW, 0, 1, MEPC),
+            MRET,
+        ],
+    );
+    put(
+        &mut image,
+        HANDLER,
+        &[csr(CSRRS, 5, 0, MCAUSE), csr(CSRRS, 6, 0, MTVAL), JAL_SELF],
+    );
+
+    put(&mut image, ROOT_TABLE, &[pte(LEAF_TABLE, PTE_V)]);
+    put(
+        &mut image,
+        LEAF_TABLE + 4,
+        &[
+            pte(CODE_PAGE, PTE_V | PTE_R | PTE_X | PTE_A),
+            pte(DATA_PAGE, PTE_V | PTE_R | PTE_A),
+        ],
+    );
+    put(&mut image, CODE_PAGE, code);
+    put(&mut image, DATA_PAGE, &[0x1234_5678]);
+
+    computer_with_bytes(Extensions::rv32i(), image)
+}
+
+#[test]
+fn loads_are_translated_and_stores_to_read_only_page_fault() {
+    let mut computer = computer_with_paging(
+        0x1000,
+        &[lui(2, 0x2), lw(3, 2, 0), sw(3, 2, 4), addi(10, 0, 1)],
+    );
+    assert_eq!(
+        computer.run_with_step_limit(Some(100)).ok(),
+        Some(RunResult::StepLimit)
+    );
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(3), 0x1234_5678);
+    assert_eq!(xregs.read(5), 15);
+    assert_eq!(xregs.read(6), 0x2004);
+    assert_eq!(xregs.read(10), 0);
+}
+
+#[test]
+fn fetch_from_unmapped_page_faults() {
+    let mut computer = computer_with_paging(0x3000, &[]);
+    assert_eq!(
+        computer.run_with_step_limit(Some(100)).ok(),
+        Some(RunResult::StepLimit)
+    );
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(5), 12);
+    assert_eq!(xregs.read(6), 0x3000);
+}
+
+#[test]
+fn execute_from_non_executable_page_faults() {
+    let mut computer = computer_with_paging(0x2000, &[]);
+    assert_eq!(
+        computer.run_with_step_limit(Some(100)).ok(),
+        Some(RunResult::StepLimit)
+    );
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(5), 12);
+    assert_eq!(xregs.read(6), 0x2000);
+}
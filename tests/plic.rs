@@ -0,0 +1,96 @@
+mod common;
+
+use common::{addi, computer_with_program, csr, lui, lw, sw, CSRRS, CSRRW, JAL_SELF, NOP};
+use simple_riscv::computer::RunResult;
+use simple_riscv::plic::{Plic, CONTEXT_MACHINE, CONTEXT_SUPERVISOR};
+use simple_riscv::processor::riscv::rv32ui::cs_register::{MCAUSE, MIE, MSTATUS, MTVEC};
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+
+const PRIORITY: u32 = 0x0;
+const ENABLE_CONTEXT0: u32 = 0x2000;
+const ENABLE_CONTEXT1: u32 = 0x2080;
+const THRESHOLD_CONTEXT0: u32 = 0x20_0000;
+const CLAIM_CONTEXT0: u32 = 0x20_0004;
+
+#[test]
+fn claim_returns_highest_priority_source() {
+    let mut plic = Plic::new();
+    plic.write32(PRIORITY + 3 * 4, 1);
+    plic.write32(PRIORITY + 7 * 4, 5);
+    plic.write32(ENABLE_CONTEXT0, 1 << 3 | 1 << 7);
+
+    plic.set_interrupt_line(3, true);
+    plic.set_interrupt_line(7, true);
+    assert!(plic.is_interrupting(CONTEXT_MACHINE));
+    assert!(!plic.is_interrupting(CONTEXT_SUPERVISOR));
+
+    assert_eq!(plic.read32(CLAIM_CONTEXT0), 7);
+    assert_eq!(plic.read32(CLAIM_CONTEXT0), 3);
+    assert_eq!(plic.read32(CLAIM_CONTEXT0), 0);
+    assert!(!plic.is_interrupting(CONTEXT_MACHINE));
+
+    // 割り込み線が上がったままならcompleteで再びpendingになる
+    plic.set_interrupt_line(3, false);
+    plic.write32(CLAIM_CONTEXT0, 3);
+    plic.write32(CLAIM_CONTEXT0, 7);
+    assert_eq!(plic.read32(CLAIM_CONTEXT0), 7);
+}
+
+#[test]
+fn threshold_masks_low_priority_sources() {
+    let mut plic = Plic::new();
+    plic.write32(PRIORITY + 4, 2);
+    plic.write32(ENABLE_CONTEXT1, 1 << 1);
+    plic.write32(THRESHOLD_CONTEXT0 + 0x1000, 2);
+
+    plic.set_interrupt_line(1, true);
+    assert!(!plic.is_interrupting(CONTEXT_SUPERVISOR));
+
+    plic.write32(THRESHOLD_CONTEXT0 + 0x1000, 1);
+    assert!(plic.is_interrupting(CONTEXT_SUPERVISOR));
+}
+
+#[test]
+fn external_interrupt_is_delivered_to_machine_mode() {
+    let mut program = vec![
+        lui(1, 0x80000),
+        addi(1, 1, 0x80),
+        csr(CSRRW, 0, 1, MTVEC),
+        lui(2, 0xc000),
+        addi(3, 0, 1),
+        sw(3, 2, 5 * 4), // priority[5] = 1
+        lui(4, 0xc002),
+        addi(3, 0, 1 << 5),
+        sw(3, 4, 0), // enable(context 0)
+        lui(1, 0x1),
+        addi(1, 1, -0x800), // MEIE
+        csr(CSRRW, 0, 1, MIE),
+        addi(1, 0, 1 << 3),
+        csr(CSRRS, 0, 1, MSTATUS),
+        JAL_SELF,
+    ];
+    program.resize(0x80 / 4, NOP);
+    program.extend([
+        csr(CSRRS, 5, 0, MCAUSE),
+        lui(6, 0xc200),
+        lw(10, 6, 4), // claim
+        sw(10, 6, 4), // complete
+        JAL_SELF,
+    ]);
+
+    let mut computer = computer_with_program(Extensions::rv32i(), &program);
+    assert_eq!(
+        computer.run_with_step_limit(Some(100)).ok(),
+        Some(RunResult::StepLimit)
+    );
+
+    computer.bus_mut().plic.set_interrupt_line(5, true);
+    assert_eq!(
+        computer.run_with_step_limit(Some(100)).ok(),
+        Some(RunResult::StepLimit)
+    );
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(5), 0x8000_000b);
+    assert_eq!(xregs.read(10), 5);
+}
@@ -0,0 +1,127 @@
+use std::path::Path;
+
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+use simple_riscv::test_suite::{run_test_image, TestOutcome};
+
+const STEP_LIMIT: u64 = 100_000;
+
+fn run(name: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("test").join(name);
+    assert_eq!(
+        run_test_image(&path, Extensions::all(), STEP_LIMIT),
+        TestOutcome::Pass,
+        "{}",
+        name
+    );
+}
+
+macro_rules! riscv_test {
+    ($test_name:ident, $file:literal) => {
+        #[test]
+        fn $test_name() {
+            run($file);
+        }
+    };
+}
+
+riscv_test!(rv32ui_p_add, "rv32ui-p-add");
+riscv_test!(rv32ui_p_addi, "rv32ui-p-addi");
+riscv_test!(rv32ui_p_and, "rv32ui-p-and");
+riscv_test!(rv32ui_p_andi, "rv32ui-p-andi");
+riscv_test!(rv32ui_p_auipc, "rv32ui-p-auipc");
+riscv_test!(rv32ui_p_beq, "rv32ui-p-beq");
+riscv_test!(rv32ui_p_bge, "rv32ui-p-bge");
+riscv_test!(rv32ui_p_bgeu, "rv32ui-p-bgeu");
+riscv_test!(rv32ui_p_blt, "rv32ui-p-blt");
+riscv_test!(rv32ui_p_bltu, "rv32ui-p-bltu");
+riscv_test!(rv32ui_p_bne, "rv32ui-p-bne");
+riscv_test!(rv32ui_p_fence_i, "rv32ui-p-fence_i");
+riscv_test!(rv32ui_p_jal, "rv32ui-p-jal");
+riscv_test!(rv32ui_p_jalr, "rv32ui-p-jalr");
+riscv_test!(rv32ui_p_lb, "rv32ui-p-lb");
+riscv_test!(rv32ui_p_lbu, "rv32ui-p-lbu");
+riscv_test!(rv32ui_p_lh, "rv32ui-p-lh");
+riscv_test!(rv32ui_p_lhu, "rv32ui-p-lhu");
+riscv_test!(rv32ui_p_lui, "rv32ui-p-lui");
+riscv_test!(rv32ui_p_lw, "rv32ui-p-lw");
+riscv_test!(rv32ui_p_ma_data, "rv32ui-p-ma_data");
+riscv_test!(rv32ui_p_or, "rv32ui-p-or");
+riscv_test!(rv32ui_p_ori, "rv32ui-p-ori");
+riscv_test!(rv32ui_p_sb, "rv32ui-p-sb");
+riscv_test!(rv32ui_p_sh, "rv32ui-p-sh");
+riscv_test!(rv32ui_p_simple, "rv32ui-p-simple");
+riscv_test!(rv32ui_p_sll, "rv32ui-p-sll");
+riscv_test!(rv32ui_p_slli, "rv32ui-p-slli");
+riscv_test!(rv32ui_p_slt, "rv32ui-p-slt");
+riscv_test!(rv32ui_p_slti, "rv32ui-p-slti");
+riscv_test!(rv32ui_p_sltiu, "rv32ui-p-sltiu");
+riscv_test!(rv32ui_p_sltu, "rv32ui-p-sltu");
+riscv_test!(rv32ui_p_sra, "rv32ui-p-sra");
+riscv_test!(rv32ui_p_srai, "rv32ui-p-srai");
+riscv_test!(rv32ui_p_srl, "rv32ui-p-srl");
+riscv_test!(rv32ui_p_srli, "rv32ui-p-srli");
+riscv_test!(rv32ui_p_sub, "rv32ui-p-sub");
+riscv_test!(rv32ui_p_sw, "rv32ui-p-sw");
+riscv_test!(rv32ui_p_xor, "rv32ui-p-xor");
+riscv_test!(rv32ui_p_xori, "rv32ui-p-xori");
+
+riscv_test!(rv32ui_v_add, "rv32ui-v-add");
+riscv_test!(rv32ui_v_addi, "rv32ui-v-addi");
+riscv_test!(rv32ui_v_and, "rv32ui-v-and");
+riscv_test!(rv32ui_v_andi, "rv32ui-v-andi");
+riscv_test!(rv32ui_v_auipc, "rv32ui-v-auipc");
+riscv_test!(rv32ui_v_beq, "rv32ui-v-beq");
+riscv_test!(rv32ui_v_bge, "rv32ui-v-bge");
+riscv_test!(rv32ui_v_bgeu, "rv32ui-v-bgeu");
+riscv_test!(rv32ui_v_blt, "rv32ui-v-blt");
+riscv_test!(rv32ui_v_bltu, "rv32ui-v-bltu");
+riscv_test!(rv32ui_v_bne, "rv32ui-v-bne");
+riscv_test!(rv32ui_v_fence_i, "rv32ui-v-fence_i");
+riscv_test!(rv32ui_v_jal, "rv32ui-v-jal");
+riscv_test!(rv32ui_v_jalr, "rv32ui-v-jalr");
+riscv_test!(rv32ui_v_lb, "rv32ui-v-lb");
+riscv_test!(rv32ui_v_lbu, "rv32ui-v-lbu");
+riscv_test!(rv32ui_v_lh, "rv32ui-v-lh");
+riscv_test!(rv32ui_v_lhu, "rv32ui-v-lhu");
+riscv_test!(rv32ui_v_lui, "rv32ui-v-lui");
+riscv_test!(rv32ui_v_lw, "rv32ui-v-lw");
+riscv_test!(rv32ui_v_ma_data, "rv32ui-v-ma_data");
+riscv_test!(rv32ui_v_or, "rv32ui-v-or");
+riscv_test!(rv32ui_v_ori, "rv32ui-v-ori");
+riscv_test!(rv32ui_v_sb, "rv32ui-v-sb");
+riscv_test!(rv32ui_v_sh, "rv32ui-v-sh");
+riscv_test!(rv32ui_v_simple, "rv32ui-v-simple");
+riscv_test!(rv32ui_v_sll, "rv32ui-v-sll");
+riscv_test!(rv32ui_v_slli, "rv32ui-v-slli");
+riscv_test!(rv32ui_v_slt, "rv32ui-v-slt");
+riscv_test!(rv32ui_v_slti, "rv32ui-v-slti");
+riscv_test!(rv32ui_v_sltiu, "rv32ui-v-sltiu");
+riscv_test!(rv32ui_v_sltu, "rv32ui-v-sltu");
+riscv_test!(rv32ui_v_sra, "rv32ui-v-sra");
+riscv_test!(rv32ui_v_srai, "rv32ui-v-srai");
+riscv_test!(rv32ui_v_srl, "rv32ui-v-srl");
+riscv_test!(rv32ui_v_srli, "rv32ui-v-srli");
+riscv_test!(rv32ui_v_sub, "rv32ui-v-sub");
+riscv_test!(rv32ui_v_sw, "rv32ui-v-sw");
+riscv_test!(rv32ui_v_xor, "rv32ui-v-xor");
+riscv_test!(rv32ui_v_xori, "rv32ui-v-xori");
+
+riscv_test!(rv32um_p_div, "rv32um-p-div");
+riscv_test!(rv32um_p_divu, "rv32um-p-divu");
+riscv_test!(rv32um_p_mul, "rv32um-p-mul");
+riscv_test!(rv32um_p_mulh, "rv32um-p-mulh");
+riscv_test!(rv32um_p_mulhsu, "rv32um-p-mulhsu");
+riscv_test!(rv32um_p_mulhu, "rv32um-p-mulhu");
+riscv_test!(rv32um_p_rem, "rv32um-p-rem");
+riscv_test!(rv32um_p_remu, "rv32um-p-remu");
+
+riscv_test!(rv32ua_p_amoadd_w, "rv32ua-p-amoadd_w");
+riscv_test!(rv32ua_p_amoand_w, "rv32ua-p-amoand_w");
+riscv_test!(rv32ua_p_amomax_w, "rv32ua-p-amomax_w");
+riscv_test!(rv32ua_p_amomaxu_w, "rv32ua-p-amomaxu_w");
+riscv_test!(rv32ua_p_amomin_w, "rv32ua-p-amomin_w");
+riscv_test!(rv32ua_p_amominu_w, "rv32ua-p-amominu_w");
+riscv_test!(rv32ua_p_amoor_w, "rv32ua-p-amoor_w");
+riscv_test!(rv32ua_p_amoswap_w, "rv32ua-p-amoswap_w");
+riscv_test!(rv32ua_p_amoxor_w, "rv32ua-p-amoxor_w");
+riscv_test!(rv32ua_p_lrsc, "rv32ua-p-lrsc");
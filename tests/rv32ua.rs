@@ -0,0 +1,37 @@
+mod common;
+
+use common::{addi, amo, computer_with_program, lui, lw, sw, ECALL};
+use simple_riscv::computer::RunResult;
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+
+const LR: u32 = 0b00010;
+const SC: u32 = 0b00011;
+
+#[test]
+fn store_conditional_fails_after_store() {
+    let mut computer = computer_with_program(
+        Extensions::all(),
+        &[
+            lui(1, 0x80001), // x1 = 0x80001000
+            addi(2, 0, 5),
+            sw(2, 1, 0),
+            amo(LR, 3, 1, 0),
+            amo(SC, 4, 1, 2), // 予約が残っているので成功
+            amo(LR, 5, 1, 0),
+            sw(0, 1, 0),
+            amo(SC, 6, 1, 2), // 間にストアがあるので失敗
+            amo(SC, 7, 1, 2), // 予約は解除済みなので失敗
+            lw(8, 1, 0),
+            ECALL,
+        ],
+    );
+    assert_eq!(computer.run().ok(), Some(RunResult::Ecall));
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(3), 5);
+    assert_eq!(xregs.read(4), 0);
+    assert_eq!(xregs.read(5), 5);
+    assert_eq!(xregs.read(6), 1);
+    assert_eq!(xregs.read(7), 1);
+    assert_eq!(xregs.read(8), 0);
+}
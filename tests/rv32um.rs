@@ -0,0 +1,16 @@
+mod common;
+
+use common::{computer_with_program, r_type, ECALL};
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+
+const MULDIV: u32 = 0b0000001;
+
+#[test]
+fn disabled_without_m_extension() {
+    let mut computer = computer_with_program(
+        Extensions::rv32i(),
+        &[r_type(MULDIV, 0b000, 3, 1, 2), ECALL],
+    );
+
+    assert!(computer.run().is_err());
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::computer_with_bytes;
+use simple_riscv::computer::RunResult;
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+
+// 16bit単位で並べたプログラム
+const PROGRAM: [u16; 10] = [
+    0x4515, // 0x00: c.li a0, 5
+    0x157d, // 0x02: c.addi a0, -1
+    0x85aa, // 0x04: c.mv a1, a0
+    0x2019, // 0x06: c.jal 0x0c
+    0x4501, // 0x08: c.li a0, 0
+    0x4501, // 0x0a: c.li a0, 0
+    0x0613, 0x0070, // 0x0c: addi a2, zero, 7
+    0x0605, // 0x10: c.addi a2, 1
+    0x0073, // 0x12: ecall (2byte境界の32bit命令)
+];
+
+fn program_bytes() -> Vec<u8> {
+    PROGRAM
+        .iter()
+        .flat_map(|half| half.to_le_bytes())
+        .chain([0, 0])
+        .collect()
+}
+
+#[test]
+fn compressed_instructions() {
+    let mut computer = computer_with_bytes(Extensions::all(), program_bytes());
+    assert_eq!(computer.run().ok(), Some(RunResult::Ecall));
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(10), 4);
+    assert_eq!(xregs.read(11), 4);
+    assert_eq!(xregs.read(1), 0x8000_0008);
+    assert_eq!(xregs.read(12), 8);
+}
+
+#[test]
+fn disabled_without_c_extension() {
+    let mut computer = computer_with_bytes(Extensions::rv32i(), program_bytes());
+
+    assert!(computer.run().is_err());
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use common::{addi, computer_with_bus, lui, lw, program_bytes, sw, ECALL};
+use simple_riscv::bus::{Bus, DRAM_BASE};
+use simple_riscv::computer::RunResult;
+use simple_riscv::config::{MachineConfig, MemoryBackend};
+use simple_riscv::device::Device;
+use simple_riscv::memory::Memory;
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+use simple_riscv::sparse_memory::SparseMemory;
+use simple_riscv::uart::UART_BASE;
+
+#[test]
+fn pages_are_allocated_on_first_write() {
+    let mut memory = SparseMemory::new(0xffff_f000);
+    assert_eq!(memory.read32(0x1234_5678).ok(), Some(0));
+    assert_eq!(memory.touched_page_offsets().count(), 0);
+
+    memory.write32(0x1234_5678, 0xcafe_babe).ok().unwrap();
+    memory.write8(0xffff_efff, 0x11).ok().unwrap();
+    memory.write16(0, 0x2233).ok().unwrap();
+    // ページをまたぐアクセス
+    memory.write32(0x0000_1ffe, 0x4455_6677).ok().unwrap();
+
+    assert_eq!(memory.read32(0x1234_5678).ok(), Some(0xcafe_babe));
+    assert_eq!(memory.read8(0xffff_efff).ok(), Some(0x11));
+    // 領域の外は読み書きできない
+    assert!(memory.read8(0xffff_f000).is_err());
+    assert!(memory.write32(0xffff_effe, 0).is_err());
+    assert_eq!(memory.read32(0x0000_1ffe).ok(), Some(0x4455_6677));
+    assert_eq!(
+        memory.touched_page_offsets().collect::<Vec<_>>(),
+        vec![
+            0x0000_0000,
+            0x0000_1000,
+            0x0000_2000,
+            0x1234_5000,
+            0xffff_e000
+        ]
+    );
+    assert_eq!(memory.page(0x1234_5000).unwrap()[0x678], 0xbe);
+    assert!(memory.page(0x5000_0000).is_none());
+}
+
+#[test]
+fn loading_zeros_does_not_touch_pages() {
+    let mut memory = SparseMemory::new(0x8000_0000);
+    let mut data = vec![0; 0x3000];
+    data[0x2001] = 1;
+
+    memory.write_bytes(0x4000_0000, &data).ok().unwrap();
+    assert_eq!(
+        memory.touched_page_offsets().collect::<Vec<_>>(),
+        vec![0x4000_2000]
+    );
+}
+
+#[test]
+fn sparse_backend_runs_program_in_large_memory() {
+    let mut config = MachineConfig::new(DRAM_BASE, 0x8000_0000).unwrap();
+    config.set_memory_backend(MemoryBackend::Sparse);
+    let program = [
+        lui(1, 0xc0000),
+        addi(4, 0, 0x7b),
+        sw(4, 1, 0),
+        lw(3, 1, 0),
+        sw(3, 2, -4),
+        ECALL,
+    ];
+
+    let bus = Bus::new(&config);
+    let mut computer = computer_with_bus(Extensions::rv32i(), bus, program_bytes(&program));
+    assert_eq!(computer.run().ok(), Some(RunResult::Ecall));
+    assert_eq!(computer.processor().xregs.read(3), 0x7b);
+
+    // プログラム、0xc000_0000、スタックの末尾の3ページ
+    assert_eq!(
+        computer.bus().touched_pages().collect::<Vec<_>>(),
+        vec![0x8000_0000, 0xc000_0000, 0xffff_f000]
+    );
+
+    let Memory::Sparse(memory) = &computer.bus().memory()[0] else {
+        panic!("sparse backend expected");
+    };
+    // 領域の中では先頭からのオフセットで数える
+    assert_eq!(
+        memory.touched_page_offsets().collect::<Vec<_>>(),
+        vec![0x0000_0000, 0x4000_0000, 0x7fff_f000]
+    );
+}
+
+#[test]
+fn sparse_memory_can_be_mapped_as_device() {
+    let mut bus = Bus::default();
+    bus.map_device(
+        0x2000_0000,
+        0x1000_0000,
+        Box::new(SparseMemory::new(0x1000_0000)),
+    )
+    .ok()
+    .unwrap();
+
+    bus.write32(0x2fff_fffc, 0x1357_9bdf).ok().unwrap();
+    assert_eq!(bus.read32(0x2fff_fffc).ok(), Some(0x1357_9bdf));
+    assert_eq!(bus.read32(0x2000_0000).ok(), Some(0));
+}
+
+#[test]
+fn sparse_memory_fills_address_space_around_devices() {
+    let mut config = MachineConfig::default();
+    config.set_memory_backend(MemoryBackend::Sparse);
+    config.fill_address_space();
+
+    let mut bus = Bus::new(&config);
+    for address in [
+        0x0000_0000,
+        0x0bff_fffc,
+        0x7fff_fffc,
+        DRAM_BASE + 0x10_0000,
+        0xffff_fffc,
+    ] {
+        bus.write32(address, address ^ 0x5555_5555).ok().unwrap();
+        assert_eq!(bus.read32(address).ok(), Some(address ^ 0x5555_5555));
+    }
+
+    // デバイスの領域はデバイスのまま
+    bus.write8(UART_BASE + 7, 0xa5).ok().unwrap();
+    assert_eq!(bus.uart.read8(7).ok(), Some(0xa5));
+    assert_eq!(config.reset_vector(), DRAM_BASE);
+}
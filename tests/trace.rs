@@ -0,0 +1,72 @@
+mod common;
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use common::{addi, computer_with_program, csr, lui, lw, CSRRW, ECALL};
+use simple_riscv::computer::RunResult;
+use simple_riscv::processor::riscv::rv32ui::cs_register::MSCRATCH;
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+use simple_riscv::trace::{self, Category, Level, TraceError};
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// トレースの設定はプロセス全体で共有するので1つのテストにまとめる
+#[test]
+fn trace_is_filtered_by_category_and_level() {
+    assert_eq!(
+        trace::configure("fetch,bogus"),
+        Err(TraceError::UnknownCategory("bogus".to_string()))
+    );
+    assert_eq!(
+        trace::configure("mem=loud"),
+        Err(TraceError::UnknownLevel("loud".to_string()))
+    );
+    // エラーになった指定は一部だけ反映されたりしない
+    assert!(!trace::enabled(Category::Fetch, Level::Info));
+
+    let buffer = SharedBuffer::default();
+    trace::set_output(Box::new(buffer.clone()));
+    trace::configure("all=info,mem,csr=trace,csr").unwrap();
+    assert!(trace::enabled(Category::Mem, Level::Debug));
+    assert!(!trace::enabled(Category::Mem, Level::Trace));
+    assert!(trace::enabled(Category::Fetch, Level::Info));
+    assert!(!trace::enabled(Category::Fetch, Level::Debug));
+    assert!(trace::enabled(Category::Csr, Level::Debug));
+    assert!(!trace::enabled(Category::Csr, Level::Trace));
+
+    let program = [
+        addi(1, 0, 0x55),
+        csr(CSRRW, 0, 1, MSCRATCH),
+        lui(2, 0x80000),
+        lw(3, 2, 0),
+        ECALL,
+    ];
+    let mut computer = computer_with_program(Extensions::rv32i(), &program);
+    assert_eq!(computer.run().ok(), Some(RunResult::Ecall));
+    trace::disable_all();
+
+    let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert!(lines.contains(&"[csr] CSR: 0x340: 0x00000000 -> 0x00000055"));
+    assert!(lines.contains(&"[mem] Mem: load 0x80000000 -> 0x05500093"));
+    assert!(lines.iter().any(|line| line.starts_with("[trap] ")));
+    // fetch/decode/execはDebugなので出力されない
+    assert!(lines
+        .iter()
+        .all(|line| !line.starts_with("[fetch]") && !line.starts_with("[decode]")));
+    assert!(lines.iter().all(|line| !line.starts_with("[exec]")));
+
+    assert!(!trace::enabled(Category::Trap, Level::Info));
+}
@@ -0,0 +1,171 @@
+mod common;
+
+use common::{
+    addi, computer_with_program, csr, lui, lw, program_with_recording_handler, sw, CSRRS, CSRRW,
+    ECALL, JAL_SELF, MRET, NOP, SRET,
+};
+use simple_riscv::computer::RunResult;
+use simple_riscv::processor::riscv::rv32ui::cs_register::{
+    MCAUSE, MEDELEG, MEPC, MSTATUS, MTVAL, MTVEC, SCAUSE, SEPC, SSTATUS, STVEC,
+};
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+
+#[test]
+fn ecall_is_handled_by_guest_and_mret_returns() {
+    let mut program = vec![
+        lui(1, 0x80000),
+        addi(1, 1, 0x40),
+        addi(1, 1, 1), // Vectoredモードでも例外はBASEに飛ぶ
+        csr(CSRRW, 0, 1, MTVEC),
+        ECALL,
+        addi(10, 0, 42),
+        JAL_SELF,
+    ];
+    program.resize(0x40 / 4, NOP);
+    program.extend([
+        csr(CSRRS, 5, 0, MCAUSE),
+        csr(CSRRS, 6, 0, MEPC),
+        addi(7, 6, 4),
+        csr(CSRRW, 0, 7, MEPC),
+        csr(CSRRS, 8, 0, MSTATUS),
+        MRET,
+    ]);
+
+    let mut computer = computer_with_program(Extensions::rv32i(), &program);
+    assert_eq!(
+        computer.run_with_step_limit(Some(100)).ok(),
+        Some(RunResult::StepLimit)
+    );
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(5), 11);
+    assert_eq!(xregs.read(6), 0x8000_0010);
+    assert_eq!(xregs.read(8) >> 11 & 0b11, 0b11);
+    assert_eq!(xregs.read(10), 42);
+}
+
+#[test]
+fn illegal_instruction_traps_with_instruction_bits() {
+    let illegal = 0xffff_ffff;
+    let program = program_with_recording_handler(&[illegal, addi(10, 0, 1)]);
+
+    let mut computer = computer_with_program(Extensions::rv32i(), &program);
+    assert_eq!(
+        computer.run_with_step_limit(Some(100)).ok(),
+        Some(RunResult::StepLimit)
+    );
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(5), 2);
+    assert_eq!(xregs.read(6), illegal);
+    assert_eq!(xregs.read(10), 1);
+}
+
+#[test]
+fn load_and_store_access_faults_report_address() {
+    let program = program_with_recording_handler(&[
+        lui(2, 0x1),
+        lw(3, 2, 4),
+        addi(11, 5, 0),
+        addi(12, 6, 0),
+        sw(3, 2, 8),
+    ]);
+
+    let mut computer = computer_with_program(Extensions::rv32i(), &program);
+    assert_eq!(
+        computer.run_with_step_limit(Some(100)).ok(),
+        Some(RunResult::StepLimit)
+    );
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(11), 5);
+    assert_eq!(xregs.read(12), 0x1004);
+    assert_eq!(xregs.read(5), 7);
+    assert_eq!(xregs.read(6), 0x1008);
+}
+
+#[test]
+fn exception_without_handler_stops_the_host() {
+    let mut computer = computer_with_program(Extensions::rv32i(), &[0xffff_ffff]);
+    assert!(computer.run().is_err());
+    let processor = computer.processor();
+
+    // トラップのCSRは変更されない
+    assert_eq!(processor.pc, 0x8000_0000);
+    assert_eq!(processor.csr.read(MCAUSE), 0);
+    assert_eq!(processor.csr.read(MTVAL), 0);
+}
+
+#[test]
+fn trap_vector_at_address_zero_is_used() {
+    let mut computer = computer_with_program(
+        Extensions::rv32i(),
+        &[csr(CSRRW, 0, 0, MTVEC), addi(10, 0, 1), ECALL],
+    );
+    assert_eq!(
+        computer.run_with_step_limit(Some(3)).ok(),
+        Some(RunResult::StepLimit)
+    );
+    let processor = computer.processor();
+
+    assert_eq!(processor.csr.read(MCAUSE), 11);
+    assert_eq!(processor.csr.read(MEPC), 0x8000_0008);
+    assert_eq!(processor.pc, 0);
+}
+
+#[test]
+fn unimp_is_an_illegal_instruction() {
+    let unimp = csr(CSRRW, 0, 0, 0xc00);
+    let program = program_with_recording_handler(&[unimp]);
+
+    let mut computer = computer_with_program(Extensions::rv32i(), &program);
+    assert_eq!(
+        computer.run_with_step_limit(Some(100)).ok(),
+        Some(RunResult::StepLimit)
+    );
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(5), 2);
+    assert_eq!(xregs.read(6), unimp);
+}
+
+#[test]
+fn user_ecall_is_delegated_to_supervisor() {
+    let mut program = vec![
+        lui(1, 0x80000),
+        addi(1, 1, 0x40),
+        csr(CSRRW, 0, 1, STVEC),
+        addi(2, 0, 1 << 8), // Uモードからのecallを委譲する
+        csr(CSRRW, 0, 2, MEDELEG),
+        lui(1, 0x80000),
+        addi(1, 1, 0x80),
+        csr(CSRRW, 0, 1, MEPC),
+        MRET, // MPP = U
+    ];
+    program.resize(0x40 / 4, NOP);
+    program.extend([
+        csr(CSRRS, 5, 0, SCAUSE),
+        csr(CSRRS, 6, 0, SSTATUS),
+        csr(CSRRS, 7, 0, SEPC),
+        addi(7, 7, 4),
+        csr(CSRRW, 0, 7, SEPC),
+        SRET,
+    ]);
+    program.resize(0x80 / 4, NOP);
+    program.extend([
+        ECALL,
+        addi(10, 0, 1),
+        csr(CSRRS, 11, 0, MSTATUS), // Uモードからは読めない
+    ]);
+
+    let mut computer = computer_with_program(Extensions::rv32i(), &program);
+    assert!(computer.run().is_err());
+    let processor = computer.processor();
+
+    assert_eq!(processor.xregs.read(5), 8);
+    assert_eq!(processor.xregs.read(6) >> 8 & 1, 0);
+    assert_eq!(processor.xregs.read(10), 1);
+    // Mモードのハンドラが無いので、不正命令の前で止まる
+    assert_eq!(processor.pc, 0x8000_0088);
+    assert_eq!(processor.csr.read(MCAUSE), 0);
+}
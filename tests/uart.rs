@@ -0,0 +1,79 @@
+mod common;
+
+use common::{addi, computer_with_program, lbu, lui, sb, ECALL};
+use simple_riscv::computer::RunResult;
+use simple_riscv::processor::riscv::rv32ui::extension::Extensions;
+use simple_riscv::uart::{Uart, UART_BASE};
+
+const RBR_THR: u32 = 0;
+const IER: u32 = 1;
+const IIR: u32 = 2;
+const LCR: u32 = 3;
+const LSR: u32 = 5;
+
+#[test]
+fn guest_output_goes_to_transmitter() {
+    let program = [
+        lui(1, UART_BASE >> 12),
+        addi(2, 0, b'h' as i32),
+        sb(2, 1, RBR_THR as i32),
+        addi(2, 0, b'i' as i32),
+        sb(2, 1, RBR_THR as i32),
+        lbu(3, 1, LSR as i32),
+        ECALL,
+    ];
+
+    let mut computer = computer_with_program(Extensions::rv32i(), &program);
+    computer.bus_mut().uart.capture_output();
+    assert_eq!(computer.run().ok(), Some(RunResult::Ecall));
+
+    assert_eq!(computer.bus_mut().uart.take_output(), b"hi");
+    // 送信は即座に終わるのでTHR empty / transmitter empty
+    assert_eq!(computer.processor().xregs.read(3), 0x60);
+}
+
+#[test]
+fn guest_reads_received_data() {
+    let program = [
+        lui(1, UART_BASE >> 12),
+        lbu(2, 1, LSR as i32),
+        lbu(3, 1, RBR_THR as i32),
+        lbu(4, 1, RBR_THR as i32),
+        lbu(5, 1, LSR as i32),
+        ECALL,
+    ];
+
+    let mut computer = computer_with_program(Extensions::rv32i(), &program);
+    computer.bus_mut().uart.receive(b"ok");
+    assert_eq!(computer.run().ok(), Some(RunResult::Ecall));
+    let xregs = &computer.processor().xregs;
+
+    assert_eq!(xregs.read(2) & 1, 1);
+    assert_eq!(xregs.read(3), b'o' as u32);
+    assert_eq!(xregs.read(4), b'k' as u32);
+    assert_eq!(xregs.read(5) & 1, 0);
+}
+
+#[test]
+fn interrupt_identification_and_divisor_latch() {
+    let mut uart = Uart::new();
+    assert!(!uart.is_interrupting());
+
+    uart.write(IER, 0b11);
+    // 送信割り込みはIIRを読むと消える
+    assert_eq!(uart.read(IIR) & 0x0f, 0x02);
+    assert!(!uart.is_interrupting());
+
+    uart.receive(b"x");
+    assert_eq!(uart.read(IIR) & 0x0f, 0x04);
+    assert_eq!(uart.read(RBR_THR), b'x');
+    assert!(!uart.is_interrupting());
+
+    // DLABが立っている間はDLL/DLMにアクセスする
+    uart.write(LCR, 0x80);
+    uart.write(RBR_THR, 0x03);
+    uart.write(IER, 0x00);
+    assert_eq!(uart.read(RBR_THR), 0x03);
+    uart.write(LCR, 0x03);
+    assert_eq!(uart.read(IER), 0b11);
+}